@@ -1,91 +1,380 @@
 use crate::{
-    io::{read_locs, read_sites, Base, Locs, Ploidy},
+    convert::{convert, convert_chunked, ChunkedOutput, ConvertOptions},
+    io::{
+        output_path,
+        plink::{plink_chromosomes, plink_files},
+        read_bed, read_locs, read_ms, read_plink, read_sample_list, read_sample_sheet, read_seqs,
+        write_file, write_freqs, Compress, Locs, Model, Ploidy, SeqFormat, Seqs, SitesIndex, Units,
+    },
+    map::{read_map_table, read_rate_map, stitch, write_hotspots_bed, MapFunction, RateMap},
+    output::{write_json, write_table, InputFile, Manifest, OutputFormat, TableFormat},
     LDhatResult as Result, SEQ_MAX,
 };
-use clap::Parser;
-use ndarray_stats::QuantileExt;
-use polars::prelude::UInt32Type;
+use clap::{Args, Parser};
+use polars::prelude::*;
 use rand::{rngs::StdRng, SeedableRng};
-use std::{fs::File, io::Write, path::PathBuf};
+use serde::Serialize;
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 pub trait Executable {
-    fn execute(&self) -> Result<()>;
+    fn execute(&self, global: &GlobalOptions) -> Result<()>;
+}
+
+/// Options shared by every subcommand.
+#[derive(Args, Debug, Serialize)]
+pub struct GlobalOptions {
+    /// Also write machine-readable summaries beside the legacy output files
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output_format: OutputFormat,
+    /// File format of the tables written with `--output-format structured`
+    #[arg(long, global = true, value_enum, default_value_t = TableFormat::Tsv)]
+    pub table_format: TableFormat,
+    /// Number of worker threads, one per core if 0. Results do not depend on it
+    #[arg(long, global = true, value_name = "INT", default_value_t = 0)]
+    pub threads: usize,
+    /// Compress the LDhat text files, reports, genetic maps and browser tracks written, adding
+    /// `.gz` or `.zst`. Tables and JSON summaries are not compressed
+    #[arg(long, global = true, value_enum)]
+    pub compress: Option<Compress>,
 }
 
 /// Convert FASTA-style file to LDhat format.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Serialize)]
 pub struct Convert {
-    /// Input FASTA-style format file.
+    /// Input alignment, FASTA-style LDhat format unless `--format` says otherwise.
     #[arg(value_name = "FILE")]
     seq: PathBuf,
-    /// SNP positions in seq file. Assumed contiguous if absent
+    /// Format of the input alignment. Guessed from the file extension if absent
+    #[arg(long, value_enum)]
+    format: Option<SeqFormat>,
+    /// Ploidy of inputs that do not record it, e.g. headerless FASTA (1 or 2)
+    #[arg(long, value_name = "INT", default_value = "1")]
+    ploidy: Ploidy,
+    /// SNP positions in seq file. Assumed contiguous if absent, ignored for ms input
     #[arg(short, long, value_name = "FILE")]
     loc: Option<PathBuf>,
+    /// Chromosome of the input: only its PLINK variants and BED intervals are read, and it is
+    /// recorded in the summary. `locs.txt` has no field for it, give it to `stitch`
+    #[arg(long, value_name = "STRING")]
+    chromosome: Option<String>,
+    /// Only keep the samples listed in this file, one ID per line
+    #[arg(long, value_name = "FILE")]
+    keep: Option<PathBuf>,
+    /// Drop the samples listed in this file, one ID per line
+    #[arg(long, value_name = "FILE")]
+    remove: Option<PathBuf>,
+    /// Tab-separated sample sheet with `sample` and `population` columns.
+    /// Writes one set of outputs per population, prefixed with `{prefix}{population}_`
+    #[arg(long, value_name = "FILE")]
+    populations: Option<PathBuf>,
+    /// Total sequence length in bp. Scales the relative positions of ms input,
+    /// and is written to locs.txt when --loc is absent
+    /// [default: number of sites, or the last position of PLINK input]
+    #[arg(long, value_name = "FLOAT")]
+    length: Option<f64>,
+    /// Crossing-over (L) or gene conversion (C) model written to locs.txt when --loc is absent
+    #[arg(long, value_name = "L|C", default_value = "L")]
+    model: Model,
+    /// Units of the positions written to locs.txt when --loc is absent, and of the positions
+    /// read with --loc. BED intervals, always in bp, are converted to these units
+    #[arg(long, value_enum, default_value_t = Units::Bp)]
+    units: Units,
     /// Only output sites with exactly two alleles
     #[arg(long, visible_alias = "2only", default_value_t = false)]
     only2: bool,
+    /// Keep the two most common alleles of multi-allelic sites and write the others as missing
+    #[arg(long, default_value_t = false)]
+    split_multiallelic: bool,
     /// Min Minor Allele Frequency (between 0 and 1)
     #[arg(long, default_value_t = 0., value_name = "FLOAT")]
     freqcut: f64,
     /// Max Missing data frequency (between 0 and 1)
     #[arg(long, default_value_t = 1., value_name = "FLOAT")]
     missfreqcut: f64,
+    /// Drop samples whose missing genotype frequency exceeds this (between 0 and 1)
+    #[arg(long, default_value_t = 1., value_name = "FLOAT")]
+    sample_missfreqcut: f64,
+    /// Drop diploid sites whose Hardy–Weinberg exact test p-value is below this
+    #[arg(long, value_name = "FLOAT")]
+    hwe: Option<f64>,
+    /// Min distance between printed sites, in the units of the locs
+    #[arg(long, default_value_t = 0., value_name = "FLOAT")]
+    min_spacing: f64,
     /// Only print sites between these two values
     #[arg(long, value_name = "INT", num_args = 2)]
     sites: Vec<usize>,
+    /// Only print sites with positions in START-END, in the units of the locs
+    #[arg(long, value_name = "START-END", value_parser = parse_region)]
+    region: Option<(f64, f64)>,
+    /// Only print sites inside the intervals of this BED file
+    #[arg(long, value_name = "FILE")]
+    include_bed: Option<PathBuf>,
+    /// Drop sites inside the intervals of this BED file
+    #[arg(long, value_name = "FILE")]
+    exclude_bed: Option<PathBuf>,
+    /// Split the printed sites into windows of this many SNPs, written as `{prefix}w{i}_`
+    #[arg(long, value_name = "INT")]
+    window_snps: Option<usize>,
+    /// Number of SNPs shared by consecutive windows
+    #[arg(
+        long,
+        value_name = "INT",
+        default_value_t = 0,
+        requires = "window_snps"
+    )]
+    window_overlap: usize,
+    /// Read an LDhat sites file this many sites at a time, bounding memory by the chunk size
+    #[arg(long, value_name = "INT")]
+    chunk_size: Option<usize>,
     /// Number of sequences to output: default=all
     #[arg(long, value_name = "INT", required = false)]
     nout: Option<usize>,
     /// Prefix of output files
     #[arg(long, value_name = "STRING", default_value = "")]
     prefix: String,
-    /// Random seed
+    /// Random seed, drawn from entropy and recorded in the manifest if absent
     #[arg(long, value_name = "INT")]
     seed: Option<u64>,
     #[clap(flatten)]
+    #[serde(skip)]
     verbose: clap_verbosity_flag::Verbosity,
 }
 
-fn sc(base: Base) -> char {
-    match base {
-        Base::N => '?',
-        Base::A => '2',
-        Base::C => '1',
-        Base::G => '0',
-        Base::T => '0',
+/// Parse `START-END` into its two positions.
+fn parse_region(s: &str) -> std::result::Result<(f64, f64), String> {
+    let parse = |p: &str| p.trim().parse::<f64>().map_err(|e| e.to_string());
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("Expected START-END, found {}", s))?;
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!("Region start {} is after its end {}", start, end));
     }
+    Ok((start, end))
 }
 
-impl Executable for Convert {
-    fn execute(&self) -> Result<()> {
-        // Original use Unix timestamp as seed. From entropy might be a better choice.
-        let mut rng: StdRng = SeedableRng::from_entropy();
-        let seqs = read_sites(&self.seq)?;
-        let (lseq, mut nseq) = seqs.shape();
-        let locs = if let Some(loc) = &self.loc {
-            read_locs(&loc)?
-        } else {
-            Locs::new_from_length(lseq)
+/// Run summary of `convert`, written as `{prefix}summary.json`.
+#[derive(Serialize, Debug)]
+struct ConvertSummary {
+    command: &'static str,
+    version: &'static str,
+    nseq: usize,
+    lseq: usize,
+    nout: usize,
+    ploidy: usize,
+    segregating_sites: usize,
+    chromosome: Option<String>,
+    outputs: Vec<PathBuf>,
+}
+
+/// Run summary of `batch`, written as `{prefix}summary.json`.
+#[derive(Serialize, Debug)]
+struct BatchSummary {
+    command: &'static str,
+    version: &'static str,
+    jobs: usize,
+    failed: usize,
+    sites: usize,
+    outputs: Vec<PathBuf>,
+}
+
+/// Run summary of `stitch`, written as `{prefix}summary.json`.
+#[derive(Serialize, Debug)]
+struct StitchSummary {
+    command: &'static str,
+    version: &'static str,
+    windows: usize,
+    snps: usize,
+    overlaps: usize,
+    inconsistent_overlaps: usize,
+    outputs: Vec<PathBuf>,
+}
+
+/// Run summary of `export`, written as `{prefix}summary.json`.
+#[derive(Serialize, Debug)]
+struct ExportSummary {
+    command: &'static str,
+    version: &'static str,
+    chromosome: String,
+    snps: usize,
+    background_rate: f64,
+    hotspots: usize,
+    /// Ne of the genetic maps, given or calibrated
+    ne: Option<f64>,
+    ne_calibrated: bool,
+    /// Length of the genetic maps in cM
+    total_cm: Option<f64>,
+    outputs: Vec<PathBuf>,
+}
+
+/// Sample lists read from `--keep`, `--remove` and `--populations`.
+struct SampleSelection {
+    keep: Option<Vec<String>>,
+    remove: Option<Vec<String>>,
+    populations: Option<Vec<(String, String)>>,
+}
+
+impl SampleSelection {
+    /// Whether `name` passes `--keep` and `--remove`.
+    fn keeps(&self, name: &str) -> bool {
+        self.keep
+            .as_ref()
+            .map_or(true, |keep| keep.iter().any(|s| s == name))
+            && !self
+                .remove
+                .as_ref()
+                .map_or(false, |remove| remove.iter().any(|s| s == name))
+    }
+
+    /// Check that every `--keep` sample is among `names` and that some sample is kept.
+    fn check(&self, names: &[&str]) -> Result<()> {
+        if let Some(keep) = &self.keep {
+            if let Some(missing) = keep.iter().find(|s| !names.contains(&s.as_str())) {
+                return Err(anyhow::anyhow!("Sample {} not found in input", missing));
+            }
+        }
+        if !names.iter().any(|name| self.keeps(name)) {
+            return Err(anyhow::anyhow!("No samples left after --keep and --remove"));
+        }
+        Ok(())
+    }
+}
+
+impl Convert {
+    fn sample_selection(&self) -> Result<SampleSelection> {
+        Ok(SampleSelection {
+            keep: self.keep.as_deref().map(read_sample_list).transpose()?,
+            remove: self.remove.as_deref().map(read_sample_list).transpose()?,
+            populations: self
+                .populations
+                .as_deref()
+                .map(read_sample_sheet)
+                .transpose()?,
+        })
+    }
+
+    /// Apply `--keep` and `--remove`, then convert every population separately
+    /// when a sample sheet is given.
+    fn convert_samples(
+        &self,
+        global: &GlobalOptions,
+        selection: &SampleSelection,
+        seqs: &Seqs,
+        locs: &Locs,
+        prefix: &str,
+        rng: &mut StdRng,
+    ) -> Result<ConvertOutputs> {
+        selection.check(&seqs.names())?;
+        let seqs = seqs.filter_samples(|name| selection.keeps(name))?;
+        let sheet = match &selection.populations {
+            Some(sheet) => sheet,
+            None => return self.convert_to_files(global, &seqs, locs, prefix, rng),
+        };
+        let population = |name: &str| {
+            sheet
+                .iter()
+                .find(|(sample, _)| sample == name)
+                .map(|(_, p)| p.as_str())
         };
-        if nseq > SEQ_MAX {
+        for name in seqs.names() {
+            if population(name).is_none() {
+                log::warn!("Sample {} has no population label, skipped", name);
+            }
+        }
+        let mut labels: Vec<&str> = vec![];
+        for (_, p) in sheet {
+            if !labels.contains(&p.as_str()) {
+                labels.push(p.as_str());
+            }
+        }
+        let mut outputs = ConvertOutputs::default();
+        for label in labels {
+            let members = seqs.filter_samples(|name| population(name) == Some(label))?;
+            if members.names().is_empty() {
+                log::warn!("Population {} has no samples in input, skipped", label);
+                continue;
+            }
+            let prefix = format!("{}{}_", prefix, label);
+            outputs.append(self.convert_to_files(global, &members, locs, &prefix, rng)?);
+        }
+        Ok(outputs)
+    }
+
+    /// Name the contig of `locs` after `--chromosome`, unless the input named it.
+    fn named_locs(&self, mut locs: Locs) -> Locs {
+        if locs.contig.is_none() {
+            locs.contig = self.chromosome.clone();
+        }
+        locs
+    }
+
+    /// Apply `--model` and `--units` to positions not read from a locs file.
+    fn generated_locs(&self, mut locs: Locs) -> Locs {
+        locs.model = self.model;
+        if locs.data.last().map_or(false, |&last| last > locs.length) {
             log::warn!(
-                "More than max no. sequences: Using first {} for analysis",
-                SEQ_MAX
+                "Sequence length {} is shorter than the last position",
+                locs.length
             );
-            nseq = SEQ_MAX;
         }
+        locs.in_units(self.units)
+    }
+
+    fn options(&self) -> Result<ConvertOptions> {
+        let bed = |path: &PathBuf| -> Result<Vec<(f64, f64)>> {
+            let bp = self.units.bp();
+            Ok(read_bed(path, self.chromosome.as_deref())?
+                .into_iter()
+                .map(|(start, end)| (start / bp, end / bp))
+                .collect())
+        };
+        Ok(ConvertOptions {
+            only2: self.only2,
+            freqcut: self.freqcut,
+            missfreqcut: self.missfreqcut,
+            sites: if self.sites.len() == 2 {
+                Some((self.sites[0], self.sites[1]))
+            } else {
+                None
+            },
+            nout: self.nout,
+            region: self.region,
+            include: self.include_bed.as_ref().map(bed).transpose()?,
+            exclude: self
+                .exclude_bed
+                .as_ref()
+                .map(bed)
+                .transpose()?
+                .unwrap_or_default(),
+            sample_missfreqcut: self.sample_missfreqcut,
+            hwe: self.hwe,
+            min_spacing: self.min_spacing,
+            split_multiallelic: self.split_multiallelic,
+        })
+    }
+
+    /// Convert one alignment, write its output files named with `prefix` and return their paths.
+    fn convert_to_files(
+        &self,
+        global: &GlobalOptions,
+        seqs: &Seqs,
+        locs: &Locs,
+        prefix: &str,
+        rng: &mut StdRng,
+    ) -> Result<ConvertOutputs> {
+        let (lseq, nseq) = seqs.shape();
         log::info!(
             "Reading {} sequences of length {} bases .........",
-            nseq,
+            core::cmp::min(nseq, SEQ_MAX),
             lseq
         );
-        let (lower, upper) = if self.sites.len() == 2 {
-            (self.sites[0], self.sites[1])
-        } else {
-            (0usize, lseq)
-        };
-        let output_sites_path = PathBuf::from(format!("{}sites.txt", self.prefix));
-        let output_locs_path = PathBuf::from(format!("{}locs.txt", self.prefix));
+        let output_sites_path = output_path(format!("{}sites.txt", prefix), global.compress);
+        let output_locs_path = output_path(format!("{}locs.txt", prefix), global.compress);
+        let output_freqs_path = output_path(format!("{}freqs.txt", prefix), global.compress);
         log::info!(
             "Segregating sites written to file	: {}",
             output_sites_path.to_str().unwrap()
@@ -94,90 +383,947 @@ impl Executable for Convert {
             "Locations of segregating sites to file	: {}",
             output_locs_path.to_str().unwrap()
         );
-        let nout = core::cmp::min(self.nout.unwrap_or(nseq), nseq);
-        let mut index = rand::seq::index::sample(&mut rng, nseq, nout).into_vec();
-        index.sort();
-        let nall = seqs
-            .allele_count(Some(self.prefix.as_str()))?
-            .to_ndarray::<UInt32Type>()?;
-        let mut minor: Vec<u8> = vec![];
-        let fl = if self.only2 || self.freqcut > 0. {
-            2
+        let mut converted = convert(seqs, locs, &self.options()?, rng)?;
+        write_file(&output_freqs_path, |ofp| {
+            write_freqs(&converted.counts, ofp)
+        })?;
+        let mut outputs = vec![output_freqs_path];
+        let mut locs_paths = vec![];
+        if let Some(size) = self.window_snps {
+            for (i, (start, end)) in converted
+                .windows(size, self.window_overlap)?
+                .into_iter()
+                .enumerate()
+            {
+                let window = converted.window(start, end);
+                let window_path =
+                    |name| output_path(format!("{}w{}_{}", prefix, i + 1, name), global.compress);
+                let sites_path = window_path("sites.txt");
+                let locs_path = window_path("locs.txt");
+                write_file(&sites_path, |ofp| window.write_sites(ofp))?;
+                write_file(&locs_path, |ofp| window.write_locs(ofp))?;
+                outputs.extend([sites_path, locs_path.clone()]);
+                locs_paths.push(locs_path);
+            }
         } else {
-            1
+            write_file(&output_sites_path, |ofp| converted.write_sites(ofp))?;
+            write_file(&output_locs_path, |ofp| converted.write_locs(ofp))?;
+            outputs.extend([output_sites_path, output_locs_path.clone()]);
+            locs_paths.push(output_locs_path);
+        }
+        let report_path = output_path(format!("{}filter_report.tsv", prefix), global.compress);
+        write_file(&report_path, |ofp| converted.write_filter_report(ofp))?;
+        outputs.push(report_path);
+        if global.output_format.is_structured() {
+            let summary_path = PathBuf::from(format!("{}summary.json", prefix));
+            let (psite, nout) = converted.seqs.shape();
+            let freqs_path = write_table(
+                &format!("{}freqs", prefix),
+                converted.counts.insert_at_idx(
+                    0,
+                    Series::new("site", (1..=lseq as u32).collect::<Vec<_>>()),
+                )?,
+                global.table_format,
+            )?;
+            let filter_path = write_table(
+                &format!("{}site_filter", prefix),
+                &mut converted.site_table()?,
+                global.table_format,
+            )?;
+            let sample_filter_path = write_table(
+                &format!("{}sample_filter", prefix),
+                &mut converted.sample_table()?,
+                global.table_format,
+            )?;
+            let summary = ConvertSummary {
+                command: "convert",
+                version: env!("CARGO_PKG_VERSION"),
+                nseq: core::cmp::min(nseq, SEQ_MAX),
+                lseq,
+                nout,
+                ploidy: seqs.ploidy as usize,
+                segregating_sites: psite,
+                chromosome: converted.locs.contig.clone(),
+                outputs: outputs
+                    .iter()
+                    .cloned()
+                    .chain([
+                        freqs_path.clone(),
+                        filter_path.clone(),
+                        sample_filter_path.clone(),
+                    ])
+                    .collect(),
+            };
+            write_json(&summary_path, &summary)?;
+            outputs.extend([freqs_path, filter_path, sample_filter_path, summary_path]);
+        }
+        Ok(ConvertOutputs {
+            paths: outputs,
+            locs: locs_paths,
+        })
+    }
+}
+
+impl Convert {
+    /// Convert an LDhat sites file `chunk_size` sites at a time, see [`convert_chunked`],
+    /// and return the paths of the outputs.
+    fn convert_chunked_to_files(
+        &self,
+        global: &GlobalOptions,
+        index: &SitesIndex,
+        locs: &Locs,
+        chunk_size: usize,
+        rng: &mut StdRng,
+    ) -> Result<ConvertOutputs> {
+        let structured = global.output_format.is_structured();
+        if structured && global.table_format != TableFormat::Tsv {
+            log::warn!("Tables are written as TSV with --chunk-size");
+        }
+        let output = ChunkedOutput {
+            prefix: &self.prefix,
+            compress: global.compress,
+            tables: structured,
         };
-        let mut output_site = vec![false; lseq];
-        let mut fsnp = vec![true; lseq];
-        for i in lower..upper {
-            let row = nall.slice(ndarray::s![i, 1usize..]);
-            let na = row.mapv(|x| (x > 0) as u32).sum();
-            let jmin = row
-                .mapv(|x| if x > 0 { x as f32 } else { f32::NAN })
-                .argmin_skipnan()?;
-            let nmin = row[jmin];
-            minor.push(jmin as u8 + 1);
-            if (fl == 1 && na > 1)
-                || (fl == 2
-                    && na == 2
-                    && nmin != nseq as u32 * seqs.ploidy as u32
-                    && nmin as f64 > (nseq * seqs.ploidy as usize) as f64 * self.freqcut)
-                    && (nall.index_axis(ndarray::Axis(0), i)[0] as f64
-                        <= (nseq * seqs.ploidy as usize) as f64 * self.missfreqcut)
-            {
-                output_site[i] = true;
-                if na > 2 {
-                    fsnp[i] = false;
+        let chunked = convert_chunked(index, locs, &self.options()?, chunk_size, &output, rng)?;
+        let mut outputs = chunked.outputs.clone();
+        if structured {
+            let sample_filter_path = write_table(
+                &format!("{}sample_filter", self.prefix),
+                &mut chunked.sample_table()?,
+                TableFormat::Tsv,
+            )?;
+            outputs.push(sample_filter_path);
+            let summary_path = PathBuf::from(format!("{}summary.json", self.prefix));
+            let summary = ConvertSummary {
+                command: "convert",
+                version: env!("CARGO_PKG_VERSION"),
+                nseq: chunked.nseq,
+                lseq: index.lseq,
+                nout: chunked.nout,
+                ploidy: index.ploidy as usize,
+                segregating_sites: chunked.psite,
+                chromosome: locs.contig.clone(),
+                outputs: outputs.clone(),
+            };
+            write_json(&summary_path, &summary)?;
+            outputs.push(summary_path);
+        }
+        Ok(ConvertOutputs {
+            paths: outputs,
+            locs: vec![chunked.locs],
+        })
+    }
+
+    /// Convert the input and write the run manifest, returning the paths of the outputs.
+    fn run(&self, global: &GlobalOptions) -> Result<ConvertOutputs> {
+        // Original use Unix timestamp as seed. Draw one from entropy so it can be recorded.
+        let seed = self.seed.unwrap_or_else(rand::random);
+        log::info!("Random seed: {}", seed);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let format = self
+            .format
+            .unwrap_or_else(|| SeqFormat::from_path(&self.seq));
+        let selection = self.sample_selection()?;
+        let mut outputs = ConvertOutputs::default();
+        if format == SeqFormat::Ms {
+            let length = self
+                .length
+                .ok_or_else(|| anyhow::anyhow!("--length is required to scale ms positions"))?;
+            for (i, replicate) in read_ms(&self.seq, length)?.enumerate() {
+                let (seqs, locs) = replicate?;
+                if seqs.len() == 0 {
+                    log::warn!("Replicate {} has no segregating sites, skipped", i + 1);
+                    continue;
                 }
+                let locs = self.named_locs(self.generated_locs(locs));
+                let prefix = format!("{}rep{}_", self.prefix, i + 1);
+                outputs.append(
+                    self.convert_samples(global, &selection, &seqs, &locs, &prefix, &mut rng)?,
+                );
+            }
+        } else if let Some(chunk_size) = self.chunk_size {
+            if format != SeqFormat::Ldhat
+                || self.populations.is_some()
+                || self.window_snps.is_some()
+            {
+                return Err(anyhow::anyhow!(
+                    "--chunk-size needs an LDhat sites file and no --populations or --window-snps"
+                ));
             }
+            let mut index = SitesIndex::build(&self.seq)?;
+            selection.check(&index.names())?;
+            index.retain(|name| selection.keeps(name));
+            let locs = if let Some(loc) = &self.loc {
+                read_locs(loc)?
+            } else {
+                let mut locs = Locs::new_from_length(index.lseq);
+                locs.length = self.length.unwrap_or(locs.length);
+                self.generated_locs(locs)
+            };
+            let locs = self.named_locs(locs);
+            outputs = self.convert_chunked_to_files(global, &index, &locs, chunk_size, &mut rng)?;
+        } else {
+            let (seqs, plink_locs) = if format == SeqFormat::Plink {
+                let (seqs, locs) = read_plink(
+                    &self.seq,
+                    self.chromosome.as_deref(),
+                    selection.keep.as_deref(),
+                )?;
+                (seqs, Some(locs))
+            } else {
+                (read_seqs(&self.seq, format, self.ploidy)?, None)
+            };
+            let locs = if let Some(loc) = &self.loc {
+                read_locs(&loc)?
+            } else if let Some(mut locs) = plink_locs {
+                locs.length = self.length.unwrap_or(locs.length);
+                self.generated_locs(locs)
+            } else {
+                let mut locs = Locs::new_from_length(seqs.len());
+                locs.length = self.length.unwrap_or(locs.length);
+                self.generated_locs(locs)
+            };
+            let locs = self.named_locs(locs);
+            outputs =
+                self.convert_samples(global, &selection, &seqs, &locs, &self.prefix, &mut rng)?;
         }
-        let psite: usize = output_site.iter().map(|&x| x as usize).sum();
-        if psite == 0 {
-            return Err(anyhow::anyhow!("No data to output"));
+        let mut inputs = if format == SeqFormat::Plink {
+            plink_files(&self.seq)
+                .iter()
+                .map(|path| InputFile::new(path))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            vec![InputFile::new(&self.seq)?]
+        };
+        for path in [
+            &self.loc,
+            &self.keep,
+            &self.remove,
+            &self.populations,
+            &self.include_bed,
+            &self.exclude_bed,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            inputs.push(InputFile::new(path)?);
         }
-        let mut ofp = File::create(output_sites_path)?;
-        writeln!(ofp, "{} {} {}", nout, psite, seqs.ploidy as usize)?;
-        let mut loc = File::create(output_locs_path)?;
-        write!(
-            loc,
-            "{} {} {}",
-            psite,
-            locs.data.last().unwrap(),
-            locs.model
+        let manifest = Manifest {
+            command: "convert",
+            version: env!("CARGO_PKG_VERSION"),
+            seed: Some(seed),
+            options: self,
+            global,
+            inputs,
+            outputs: outputs.paths.clone(),
+        };
+        write_json(
+            &PathBuf::from(format!("{}manifest.json", self.prefix)),
+            &manifest,
         )?;
+        Ok(outputs)
+    }
+}
+
+impl Executable for Convert {
+    fn execute(&self, global: &GlobalOptions) -> Result<()> {
+        self.run(global)?;
+        Ok(())
+    }
+}
+
+/// Files written by one `convert` run.
+#[derive(Debug, Default)]
+struct ConvertOutputs {
+    /// Every file written
+    paths: Vec<PathBuf>,
+    /// The `locs.txt` files among `paths`, one per alignment or window
+    locs: Vec<PathBuf>,
+}
+
+impl ConvertOutputs {
+    fn append(&mut self, other: ConvertOutputs) {
+        self.paths.extend(other.paths);
+        self.locs.extend(other.locs);
+    }
+}
+
+/// Run `convert` on every chromosome of a genome and merge the kept sites into one table.
+///
+/// Options after `--` are passed to every `convert` job, whose outputs are prefixed with
+/// `{prefix}{chromosome}_`. Add `--window-snps` there to split chromosomes into windows.
+/// Every job is given `--chromosome {chromosome}`, so BED intervals apply to their own
+/// chromosome and each job summary records it.
+#[derive(Parser, Debug, Serialize)]
+pub struct Batch {
+    /// Per-chromosome inputs named `{chromosome}.*`, or one PLINK fileset split by chromosome
+    #[arg(value_name = "FILE", required = true)]
+    inputs: Vec<PathBuf>,
+    /// Number of jobs run at the same time
+    #[arg(long, value_name = "INT", default_value_t = 1)]
+    jobs: usize,
+    /// Number of times a failed job is retried
+    #[arg(long, value_name = "INT", default_value_t = 0)]
+    retries: usize,
+    /// Prefix of output files
+    #[arg(long, value_name = "STRING", default_value = "")]
+    prefix: String,
+    /// Options of `convert`, shared by every job
+    #[arg(last = true, value_name = "CONVERT OPTIONS")]
+    convert: Vec<String>,
+    #[clap(flatten)]
+    #[serde(skip)]
+    verbose: clap_verbosity_flag::Verbosity,
+}
+
+/// One `convert` run of a batch.
+#[derive(Debug)]
+struct Job {
+    chromosome: String,
+    input: PathBuf,
+    /// Whether `input` is a multi-chromosome PLINK fileset to take `chromosome` from
+    plink: bool,
+}
+
+/// Outcome of a [`Job`] after its retries.
+struct JobResult {
+    attempts: usize,
+    outputs: Result<ConvertOutputs>,
+}
+
+/// Positions kept in the `locs` files of one job, sorted and without the duplicates of
+/// overlapping windows.
+fn kept_positions(locs: &[PathBuf]) -> Result<Vec<f64>> {
+    let mut kept = vec![];
+    for path in locs {
+        kept.extend(read_locs(path)?.data);
+    }
+    kept.sort_by(f64::total_cmp);
+    kept.dedup();
+    Ok(kept)
+}
+
+/// Contig name of a per-chromosome file, its file name up to the first dot.
+fn contig_name(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.split('.').next().unwrap_or_default().to_string()
+}
+
+impl Batch {
+    fn plan(&self) -> Result<Vec<Job>> {
+        if let [input] = self.inputs.as_slice() {
+            if SeqFormat::from_path(input) == SeqFormat::Plink {
+                return Ok(plink_chromosomes(input)?
+                    .into_iter()
+                    .map(|chromosome| Job {
+                        chromosome,
+                        input: input.clone(),
+                        plink: true,
+                    })
+                    .collect());
+            }
+        }
+        let jobs: Vec<Job> = self
+            .inputs
+            .iter()
+            .map(|input| Job {
+                chromosome: contig_name(input),
+                input: input.clone(),
+                plink: false,
+            })
+            .collect();
+        for (i, job) in jobs.iter().enumerate() {
+            if jobs[..i].iter().any(|j| j.chromosome == job.chromosome) {
+                return Err(anyhow::anyhow!(
+                    "Two inputs are named after chromosome {}",
+                    job.chromosome
+                ));
+            }
+        }
+        Ok(jobs)
+    }
 
-        for i in index {
-            let seq = seqs.data.select_at_idx(i).unwrap();
-            ofp.write(format!(">{}\n", seq.name()).as_bytes())?;
-            let seq: Vec<Option<u8>> = seq.u8().expect("").into_iter().collect();
-            let mut na = 0;
-            for (j, &o) in &mut output_site.iter().enumerate() {
-                if o {
-                    let a = Base::from(seq[j].unwrap());
-                    let to_write = if seqs.ploidy == Ploidy::Diploid {
-                        sc(a).to_string()
-                    } else if !fsnp[j] {
-                        a.to_string()
-                    } else if fsnp[j] && a == Base::N {
-                        "?".to_string()
-                    } else {
-                        (a as usize).to_string()
-                    };
-                    ofp.write(to_write.as_bytes())?;
-                    na += 1;
-                    if (na % 50) == 0 {
-                        ofp.write(b"\n")?;
+    fn job_command(&self, job: &Job) -> Result<Convert> {
+        let mut args = vec![
+            "convert".to_string(),
+            job.input.display().to_string(),
+            "--prefix".to_string(),
+            format!("{}{}_", self.prefix, job.chromosome),
+            "--chromosome".to_string(),
+            job.chromosome.clone(),
+        ];
+        args.extend(self.convert.iter().cloned());
+        Ok(Convert::try_parse_from(args)?)
+    }
+
+    /// Call `run` until it succeeds or `--retries` is exhausted.
+    fn run_job(&self, job: &Job, run: impl Fn() -> Result<ConvertOutputs>) -> JobResult {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            // A panic in one job is recorded as its failure rather than aborting the batch
+            let outputs = std::panic::catch_unwind(AssertUnwindSafe(&run)).unwrap_or_else(|e| {
+                let message = e
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| e.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                Err(anyhow::anyhow!("panicked: {}", message))
+            });
+            match outputs {
+                Ok(outputs) => {
+                    return JobResult {
+                        attempts,
+                        outputs: Ok(outputs),
+                    }
+                }
+                Err(e) if attempts <= self.retries => {
+                    log::warn!("Chromosome {} failed, retrying: {}", job.chromosome, e)
+                }
+                Err(e) => {
+                    return JobResult {
+                        attempts,
+                        outputs: Err(e),
                     }
                 }
             }
-            if (na % 50) != 0 {
-                ofp.write(b"\n")?;
+        }
+    }
+}
+
+impl Executable for Batch {
+    fn execute(&self, global: &GlobalOptions) -> Result<()> {
+        let plan = self.plan()?;
+        // Usage errors in the shared options are reported once, not retried for every job
+        let commands = plan
+            .iter()
+            .map(|job| self.job_command(job))
+            .collect::<Result<Vec<_>>>()?;
+        log::info!("{} jobs planned", plan.len());
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<JobResult>>> =
+            Mutex::new(plan.iter().map(|_| None).collect());
+        std::thread::scope(|scope| {
+            for _ in 0..self.jobs.clamp(1, plan.len().max(1)) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= plan.len() {
+                        break;
+                    }
+                    let result = self.run_job(&plan[i], || commands[i].run(global));
+                    results.lock().unwrap()[i] = Some(result);
+                });
+            }
+        });
+        let results: Vec<JobResult> = results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(Option::unwrap)
+            .collect();
+
+        let mut chromosomes = vec![];
+        let mut positions = vec![];
+        let mut errors = vec![];
+        let mut failed = 0;
+        for (job, result) in plan.iter().zip(&results) {
+            let kept = result
+                .outputs
+                .as_ref()
+                .map_err(|e| e.to_string())
+                .and_then(|outputs| {
+                    kept_positions(&outputs.locs)
+                        .map_err(|e| format!("Reading the kept sites: {}", e))
+                });
+            match kept {
+                Ok(kept) => {
+                    chromosomes.extend(kept.iter().map(|_| job.chromosome.clone()));
+                    positions.extend(kept);
+                    errors.push(None);
+                }
+                Err(e) => {
+                    failed += 1;
+                    log::error!(
+                        "Chromosome {} failed after {} attempts: {}",
+                        job.chromosome,
+                        result.attempts,
+                        e
+                    );
+                    errors.push(Some(e));
+                }
+            }
+        }
+        let sites = positions.len();
+        let batch_path = write_table(
+            &format!("{}batch", self.prefix),
+            &mut df!(
+                "chromosome" => plan.iter().map(|j| j.chromosome.as_str()).collect::<Vec<_>>(),
+                "input" => plan.iter().map(|j| j.input.display().to_string()).collect::<Vec<_>>(),
+                "attempts" => results.iter().map(|r| r.attempts as u32).collect::<Vec<_>>(),
+                "error" => errors,
+            )?,
+            global.table_format,
+        )?;
+        let sites_path = write_table(
+            &format!("{}sites", self.prefix),
+            &mut df!("chromosome" => chromosomes, "position" => positions)?,
+            global.table_format,
+        )?;
+        let mut outputs = vec![batch_path, sites_path];
+        if global.output_format.is_structured() {
+            let summary = BatchSummary {
+                command: "batch",
+                version: env!("CARGO_PKG_VERSION"),
+                jobs: plan.len(),
+                failed,
+                sites,
+                outputs: outputs.clone(),
+            };
+            let summary_path = PathBuf::from(format!("{}summary.json", self.prefix));
+            write_json(&summary_path, &summary)?;
+            outputs.push(summary_path);
+        }
+        // Every job also writes its own manifest, with the seed it used
+        for result in &results {
+            if let Ok(job_outputs) = &result.outputs {
+                outputs.extend(job_outputs.paths.iter().cloned());
             }
         }
-        for (i, &o) in &mut output_site.iter().enumerate() {
-            if o {
-                write!(loc, "\n{:.3}", locs.data[i])?;
+        let mut inputs = vec![];
+        for job in &plan {
+            if job.plink {
+                // Every job of a PLINK split reads the same fileset
+                if inputs.is_empty() {
+                    for path in plink_files(&job.input) {
+                        inputs.push(InputFile::new(&path)?);
+                    }
+                }
+            } else {
+                inputs.push(InputFile::new(&job.input)?);
             }
         }
+        let manifest = Manifest {
+            command: "batch",
+            version: env!("CARGO_PKG_VERSION"),
+            seed: None,
+            options: self,
+            global,
+            inputs,
+            outputs,
+        };
+        write_json(
+            &PathBuf::from(format!("{}manifest.json", self.prefix)),
+            &manifest,
+        )?;
+        if failed > 0 {
+            return Err(anyhow::anyhow!("{} of {} jobs failed", failed, plan.len()));
+        }
         Ok(())
     }
 }
+
+/// Join the rate maps of overlapping windows into one chromosome map.
+#[derive(Parser, Debug, Serialize)]
+pub struct Stitch {
+    /// Rate summary of each window in chromosome order, such as `res.txt` of LDhat `stat`
+    #[arg(long = "map", value_name = "FILE", required = true)]
+    maps: Vec<PathBuf>,
+    /// SNP positions of each window, in the same order as `--map`
+    #[arg(long = "loc", value_name = "FILE", required = true)]
+    locs: Vec<PathBuf>,
+    /// Number of SNPs shared by consecutive windows, found from the positions if absent
+    #[arg(long, value_name = "INT")]
+    overlap: Option<usize>,
+    /// Max relative difference of the windows' total rate over an overlap
+    #[arg(long, value_name = "FLOAT", default_value_t = 0.5)]
+    tolerance: f64,
+    /// Fail instead of warning when an overlap exceeds `--tolerance`
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+    /// Chromosome of the windows, kept in the map for `export`
+    #[arg(long, value_name = "STRING")]
+    chromosome: Option<String>,
+    /// Prefix of output files
+    #[arg(long, value_name = "STRING", default_value = "")]
+    prefix: String,
+    #[clap(flatten)]
+    #[serde(skip)]
+    verbose: clap_verbosity_flag::Verbosity,
+}
+
+impl Executable for Stitch {
+    fn execute(&self, global: &GlobalOptions) -> Result<()> {
+        if self.maps.len() != self.locs.len() {
+            return Err(anyhow::anyhow!(
+                "{} rate maps but {} locs files",
+                self.maps.len(),
+                self.locs.len()
+            ));
+        }
+        let windows = self
+            .maps
+            .iter()
+            .zip(&self.locs)
+            .map(|(map, loc)| read_rate_map(map, &read_locs(loc)?))
+            .collect::<Result<Vec<_>>>()?;
+        let (mut map, overlaps) = stitch(&windows, self.overlap)?;
+        if self.chromosome.is_some() {
+            map.contig = self.chromosome.clone();
+        }
+        let mut inconsistent = 0;
+        for (i, overlap) in overlaps.iter().enumerate() {
+            if overlap.relative_difference() > self.tolerance {
+                inconsistent += 1;
+                log::warn!(
+                    "Windows {} and {} differ by {:.1}% over {}-{}",
+                    i + 1,
+                    i + 2,
+                    overlap.relative_difference() * 100.,
+                    overlap.start,
+                    overlap.end
+                );
+            }
+        }
+        let overlaps_path = write_table(
+            &format!("{}overlaps", self.prefix),
+            &mut df!(
+                "window" => (1..=overlaps.len() as u32).collect::<Vec<_>>(),
+                "start" => overlaps.iter().map(|o| o.start).collect::<Vec<_>>(),
+                "end" => overlaps.iter().map(|o| o.end).collect::<Vec<_>>(),
+                "cut" => overlaps.iter().map(|o| o.cut).collect::<Vec<_>>(),
+                "left" => overlaps.iter().map(|o| o.left).collect::<Vec<_>>(),
+                "right" => overlaps.iter().map(|o| o.right).collect::<Vec<_>>(),
+                "relative_difference" => overlaps
+                    .iter()
+                    .map(|o| o.relative_difference())
+                    .collect::<Vec<_>>(),
+            )?,
+            global.table_format,
+        )?;
+        if self.strict && inconsistent > 0 {
+            return Err(anyhow::anyhow!(
+                "{} overlaps exceed --tolerance {}",
+                inconsistent,
+                self.tolerance
+            ));
+        }
+        let path = write_table(
+            &format!("{}map", self.prefix),
+            &mut map.table()?,
+            global.table_format,
+        )?;
+        log::info!(
+            "Map of {} SNPs from {} windows written to {}",
+            map.positions.len(),
+            windows.len(),
+            path.display()
+        );
+        let mut outputs = vec![overlaps_path, path];
+        if global.output_format.is_structured() {
+            let summary = StitchSummary {
+                command: "stitch",
+                version: env!("CARGO_PKG_VERSION"),
+                windows: windows.len(),
+                snps: map.positions.len(),
+                overlaps: overlaps.len(),
+                inconsistent_overlaps: inconsistent,
+                outputs: outputs.clone(),
+            };
+            let summary_path = PathBuf::from(format!("{}summary.json", self.prefix));
+            write_json(&summary_path, &summary)?;
+            outputs.push(summary_path);
+        }
+        let manifest = Manifest {
+            command: "stitch",
+            version: env!("CARGO_PKG_VERSION"),
+            seed: None,
+            options: self,
+            global,
+            inputs: self
+                .maps
+                .iter()
+                .chain(&self.locs)
+                .map(|path| InputFile::new(path))
+                .collect::<Result<_>>()?,
+            outputs,
+        };
+        write_json(
+            &PathBuf::from(format!("{}manifest.json", self.prefix)),
+            &manifest,
+        )?;
+        Ok(())
+    }
+}
+
+/// Export a recombination rate map as browser tracks and genetic maps.
+///
+/// Always writes the rate per interval as `{prefix}rates.bedGraph` and the hotspots as
+/// `{prefix}hotspots.bed`. With `--ne` or `--total-cm`, also writes genetic maps in
+/// centimorgans as `{prefix}genetic_map.txt` (HapMap) and `{prefix}plink.map`.
+#[derive(Parser, Debug, Serialize)]
+pub struct Export {
+    /// Rate map written by `stitch`, or the rate summary of LDhat `stat` with `--loc`
+    #[arg(value_name = "FILE")]
+    map: PathBuf,
+    /// SNP positions of an LDhat rate summary
+    #[arg(short, long, value_name = "FILE")]
+    loc: Option<PathBuf>,
+    /// Effective population size, to turn rho = 4 Ne r into recombination fractions
+    #[arg(long, value_name = "FLOAT")]
+    ne: Option<f64>,
+    /// Known total map length in cM, to calibrate Ne against instead
+    #[arg(long, value_name = "FLOAT", conflicts_with = "ne")]
+    total_cm: Option<f64>,
+    /// Map function from recombination fraction to map distance
+    #[arg(long, value_enum, default_value_t = MapFunction::Haldane)]
+    map_function: MapFunction,
+    /// Units of the map positions
+    #[arg(long, value_enum, default_value_t = Units::Bp)]
+    units: Units,
+    /// Chromosome written to the output files, required unless the map records one
+    #[arg(long, value_name = "STRING")]
+    chromosome: Option<String>,
+    /// Call hotspots where the rate is at least this many times the background rate
+    #[arg(long, value_name = "FLOAT", default_value_t = 5.)]
+    hotspot_fold: f64,
+    /// Prefix of output files
+    #[arg(long, value_name = "STRING", default_value = "")]
+    prefix: String,
+    #[clap(flatten)]
+    #[serde(skip)]
+    verbose: clap_verbosity_flag::Verbosity,
+}
+
+impl Export {
+    /// Write the HapMap and PLINK genetic maps of `map` for the given Ne,
+    /// returning their paths and the map length in cM.
+    fn write_genetic_maps(
+        &self,
+        global: &GlobalOptions,
+        map: &RateMap,
+        ne: f64,
+        chromosome: &str,
+        scale: f64,
+    ) -> Result<(Vec<PathBuf>, f64)> {
+        let cm = map.genetic_map(ne, self.map_function)?;
+        let bp: Vec<u64> = map
+            .positions
+            .iter()
+            .map(|p| (p * scale).round() as u64)
+            .collect();
+
+        let hapmap_path = output_path(format!("{}genetic_map.txt", self.prefix), global.compress);
+        write_file(&hapmap_path, |hapmap| {
+            writeln!(hapmap, "Chromosome\tPosition(bp)\tRate(cM/Mb)\tMap(cM)")?;
+            for k in 0..bp.len() {
+                // The rate of the interval starting at each SNP, 0 after the last
+                let rate = match bp.get(k + 1) {
+                    Some(&next) if next > bp[k] => {
+                        (cm[k + 1] - cm[k]) / (next - bp[k]) as f64 * 1e6
+                    }
+                    _ => 0.,
+                };
+                writeln!(
+                    hapmap,
+                    "{}\t{}\t{:.6}\t{:.6}",
+                    chromosome, bp[k], rate, cm[k]
+                )?;
+            }
+            Ok(())
+        })?;
+
+        let plink_path = output_path(format!("{}plink.map", self.prefix), global.compress);
+        write_file(&plink_path, |plink| {
+            for (position, cm) in bp.iter().zip(&cm) {
+                writeln!(
+                    plink,
+                    "{}\t{}:{}\t{:.6}\t{}",
+                    chromosome, chromosome, position, cm, position
+                )?;
+            }
+            Ok(())
+        })?;
+        let total_cm = *cm.last().unwrap();
+        log::info!(
+            "Genetic maps of {:.3} cM written to {} and {}",
+            total_cm,
+            hapmap_path.display(),
+            plink_path.display()
+        );
+        Ok((vec![hapmap_path, plink_path], total_cm))
+    }
+}
+
+impl Executable for Export {
+    fn execute(&self, global: &GlobalOptions) -> Result<()> {
+        let map = match &self.loc {
+            Some(loc) => read_rate_map(&self.map, &read_locs(loc)?)?,
+            None => read_map_table(&self.map)?,
+        };
+        let chromosome = self
+            .chromosome
+            .as_deref()
+            .or(map.contig.as_deref())
+            .ok_or_else(|| {
+                anyhow::anyhow!("The map records no chromosome, give it with --chromosome")
+            })?;
+        let scale = self.units.bp();
+
+        let bedgraph_path = output_path(format!("{}rates.bedGraph", self.prefix), global.compress);
+        write_file(&bedgraph_path, |ofp| {
+            map.write_bedgraph(chromosome, scale, ofp)
+        })?;
+        let hotspots = map.hotspots(self.hotspot_fold);
+        let bed_path = output_path(format!("{}hotspots.bed", self.prefix), global.compress);
+        write_file(&bed_path, |ofp| {
+            write_hotspots_bed(&hotspots, chromosome, scale, ofp)
+        })?;
+        log::info!(
+            "{} hotspots above {} times the background rate written to {}",
+            hotspots.len(),
+            self.hotspot_fold,
+            bed_path.display()
+        );
+        let mut outputs = vec![bedgraph_path, bed_path];
+
+        let ne = match (self.ne, self.total_cm) {
+            (Some(ne), _) => Some(ne),
+            (None, Some(total_cm)) => {
+                let ne = map.calibrate_ne(total_cm, self.map_function)?;
+                log::info!("Ne calibrated to {:.1} for a {} cM map", ne, total_cm);
+                Some(ne)
+            }
+            (None, None) => {
+                log::info!("No --ne or --total-cm, genetic maps not written");
+                None
+            }
+        };
+        let mut total_cm = None;
+        if let Some(ne) = ne {
+            let (paths, cm) = self.write_genetic_maps(global, &map, ne, chromosome, scale)?;
+            outputs.extend(paths);
+            total_cm = Some(cm);
+        }
+        if global.output_format.is_structured() {
+            let summary = ExportSummary {
+                command: "export",
+                version: env!("CARGO_PKG_VERSION"),
+                chromosome: chromosome.to_string(),
+                snps: map.positions.len(),
+                background_rate: map.background(),
+                hotspots: hotspots.len(),
+                ne,
+                ne_calibrated: self.ne.is_none() && ne.is_some(),
+                total_cm,
+                outputs: outputs.clone(),
+            };
+            let summary_path = PathBuf::from(format!("{}summary.json", self.prefix));
+            write_json(&summary_path, &summary)?;
+            outputs.push(summary_path);
+        }
+
+        let manifest = Manifest {
+            command: "export",
+            version: env!("CARGO_PKG_VERSION"),
+            seed: None,
+            options: self,
+            global,
+            inputs: [Some(&self.map), self.loc.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(|path| InputFile::new(path))
+                .collect::<Result<_>>()?,
+            outputs,
+        };
+        write_json(
+            &PathBuf::from(format!("{}manifest.json", self.prefix)),
+            &manifest,
+        )?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_convert_compress() {
+    use std::io::Read;
+    let dir = std::env::temp_dir();
+    let input = dir.join("ldhat_test_compress_input.txt");
+    std::fs::write(
+        &input,
+        "3 4 1\n>SampleA\nTCCG\n>SampleB\nTACG\n>SampleC\nTCCA\n",
+    )
+    .unwrap();
+    let prefix = format!("{}/ldhat_test_compress_", dir.display());
+    let global = GlobalOptions {
+        output_format: OutputFormat::Text,
+        table_format: TableFormat::Tsv,
+        threads: 0,
+        compress: Some(Compress::Gz),
+    };
+    let args = ["convert", input.to_str().unwrap(), "--prefix", &prefix];
+    let outputs = Convert::try_parse_from(args).unwrap().run(&global).unwrap();
+    let sites = PathBuf::from(format!("{}sites.txt.gz", prefix));
+    assert!(outputs.paths.contains(&sites));
+    assert_eq!(
+        outputs.locs,
+        vec![PathBuf::from(format!("{}locs.txt.gz", prefix))]
+    );
+    let mut content = String::new();
+    crate::io::open(&sites)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert!(content.starts_with("3 2 1\n>SampleA\n"));
+}
+
+#[test]
+fn test_batch_plan() {
+    let batch = Batch::try_parse_from(["batch", "data/chr1.sites.txt", "other/chr1.fa"]).unwrap();
+    assert!(batch.plan().is_err());
+    let batch = Batch::try_parse_from(["batch", "chr1.sites.txt", "chr2.sites.txt"]).unwrap();
+    let plan = batch.plan().unwrap();
+    let command = batch.job_command(&plan[1]).unwrap();
+    assert_eq!(command.chromosome.as_deref(), Some("chr2"));
+
+    let prefix = std::env::temp_dir().join("ldhat_test_batch_plink");
+    std::fs::write(
+        prefix.with_extension("bim"),
+        "1 rs1 0 100 A G\n2 rs2 0 50 A C\n1 rs3 0 250 C T\n",
+    )
+    .unwrap();
+    let bed = prefix.with_extension("bed");
+    let batch = Batch::try_parse_from(["batch", bed.to_str().unwrap()]).unwrap();
+    let plan = batch.plan().unwrap();
+    let chromosomes: Vec<&str> = plan.iter().map(|j| j.chromosome.as_str()).collect();
+    assert_eq!(chromosomes, vec!["1", "2"]);
+    assert!(plan.iter().all(|j| j.plink && j.input == bed));
+    let command = batch.job_command(&plan[1]).unwrap();
+    assert_eq!(command.chromosome.as_deref(), Some("2"));
+}
+
+#[test]
+fn test_batch_run_job() {
+    use std::cell::Cell;
+    let batch = Batch::try_parse_from(["batch", "chr1.sites.txt", "--retries", "2"]).unwrap();
+    let plan = batch.plan().unwrap();
+    let job = &plan[0];
+    let calls = Cell::new(0);
+    let result = batch.run_job(job, || {
+        calls.set(calls.get() + 1);
+        if calls.get() < 3 {
+            Err(anyhow::anyhow!("flaky"))
+        } else {
+            Ok(ConvertOutputs::default())
+        }
+    });
+    assert_eq!(result.attempts, 3);
+    assert!(result.outputs.is_ok());
+    let result = batch.run_job(job, || Err(anyhow::anyhow!("broken")));
+    assert_eq!(result.attempts, 3);
+    assert_eq!(result.outputs.unwrap_err().to_string(), "broken");
+    let result = batch.run_job(job, || panic!("out of bounds"));
+    assert_eq!(
+        result.outputs.unwrap_err().to_string(),
+        "panicked: out of bounds"
+    );
+}
@@ -0,0 +1,804 @@
+use crate::{
+    io::{allele_table, Base, Genotypes, Locs, Ploidy, Seqs},
+    LDhatResult as Result, SEQ_MAX,
+};
+use ndarray_stats::QuantileExt;
+use polars::prelude::*;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io::Write;
+
+mod stream;
+pub use stream::{convert_chunked, Chunked, ChunkedOutput};
+
+/// Settings of [`convert`], the library counterpart of the `convert` subcommand.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertOptions {
+    /// Only output sites with exactly two alleles
+    pub only2: bool,
+    /// Min Minor Allele Frequency (between 0 and 1)
+    pub freqcut: f64,
+    /// Max Missing data frequency (between 0 and 1)
+    pub missfreqcut: f64,
+    /// Only keep sites with column index in `lower..upper`
+    pub sites: Option<(usize, usize)>,
+    /// Number of sequences to output, all if `None`
+    pub nout: Option<usize>,
+    /// Only keep sites with positions in `start..=end`
+    pub region: Option<(f64, f64)>,
+    /// Only keep sites inside one of these BED-style `(start, end]` intervals
+    pub include: Option<Vec<(f64, f64)>>,
+    /// Drop sites inside any of these BED-style `(start, end]` intervals
+    pub exclude: Vec<(f64, f64)>,
+    /// Max missing genotype frequency of a sample (between 0 and 1)
+    pub sample_missfreqcut: f64,
+    /// Min p-value of the Hardy–Weinberg exact test of diploid biallelic sites
+    pub hwe: Option<f64>,
+    /// Min distance between consecutive kept sites, in the units of the locs
+    pub min_spacing: f64,
+    /// Keep the two most common alleles of multi-allelic sites, the others as missing
+    pub split_multiallelic: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            only2: false,
+            freqcut: 0.,
+            missfreqcut: 1.,
+            sites: None,
+            nout: None,
+            region: None,
+            include: None,
+            exclude: vec![],
+            sample_missfreqcut: 1.,
+            hwe: None,
+            min_spacing: 0.,
+            split_multiallelic: false,
+        }
+    }
+}
+
+impl ConvertOptions {
+    /// Whether `position` lies within `region`, `include` and outside `exclude`.
+    fn in_region(&self, position: f64) -> bool {
+        let within = |&(start, end): &(f64, f64)| start < position && position <= end;
+        self.region
+            .map_or(true, |(start, end)| start <= position && position <= end)
+            && self
+                .include
+                .as_ref()
+                .map_or(true, |intervals| intervals.iter().any(within))
+            && !self.exclude.iter().any(within)
+    }
+}
+
+/// Decision taken for one input site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiteFilter {
+    Pass,
+    OutOfRange,
+    OutOfRegion,
+    Monomorphic,
+    NotBiallelic,
+    MinorFrequency,
+    Missing,
+    HardyWeinberg,
+    Spacing,
+}
+
+impl std::fmt::Display for SiteFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SiteFilter::Pass => write!(f, "pass"),
+            SiteFilter::OutOfRange => write!(f, "out_of_range"),
+            SiteFilter::OutOfRegion => write!(f, "out_of_region"),
+            SiteFilter::Monomorphic => write!(f, "monomorphic"),
+            SiteFilter::NotBiallelic => write!(f, "not_biallelic"),
+            SiteFilter::MinorFrequency => write!(f, "minor_frequency"),
+            SiteFilter::Missing => write!(f, "missing"),
+            SiteFilter::HardyWeinberg => write!(f, "hardy_weinberg"),
+            SiteFilter::Spacing => write!(f, "spacing"),
+        }
+    }
+}
+
+/// Decision taken for one input sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFilter {
+    Pass,
+    Missing,
+}
+
+impl std::fmt::Display for SampleFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SampleFilter::Pass => write!(f, "pass"),
+            SampleFilter::Missing => write!(f, "missing"),
+        }
+    }
+}
+
+/// Missing genotypes of one sample behind a [`SampleFilter`] decision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleReport {
+    pub name: String,
+    pub missing: u32,
+    pub filter: SampleFilter,
+}
+
+/// Per-site statistics behind a [`SiteFilter`] decision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiteReport {
+    pub position: f64,
+    pub missing: u32,
+    pub alleles: u32,
+    /// [`Base`] code of the least frequent allele, written as `1` for haploid biallelic sites
+    pub minor: u8,
+    /// [`Base`] codes of the two alleles kept when a multi-allelic site was split
+    pub split: Option<[u8; 2]>,
+    pub filter: SiteFilter,
+}
+
+/// Result of [`convert`]: the kept samples and sites, and why every site was kept or not.
+pub struct Converted {
+    /// Sampled sequences restricted to the kept sites
+    pub seqs: Seqs,
+    /// Positions of the kept sites
+    pub locs: Locs,
+    /// Allele counts of every input site, see [`Seqs::allele_count`]
+    pub counts: DataFrame,
+    /// One report per input site
+    pub reports: Vec<SiteReport>,
+    /// One report per input sample
+    pub samples: Vec<SampleReport>,
+}
+
+fn sc(base: Base) -> char {
+    match base {
+        Base::N => '?',
+        Base::A => '2',
+        Base::C => '1',
+        Base::G => '?',
+        Base::T => '0',
+    }
+}
+
+/// Character written to `sites.txt` for `base` at a kept site described by `report`.
+fn site_char(ploidy: Ploidy, report: &SiteReport, base: Base) -> u8 {
+    let base = match report.split {
+        Some(kept) => Base::from(split_call(ploidy, kept, base as u8)),
+        None => base,
+    };
+    if ploidy == Ploidy::Diploid {
+        sc(base) as u8
+    } else if report.alleles > 2 {
+        base.to_string().as_bytes()[0]
+    } else if base == Base::N {
+        b'?'
+    } else if base as u8 == report.minor {
+        b'1'
+    } else {
+        b'0'
+    }
+}
+
+/// Call `value` at a site split into the `kept` alleles: other alleles become missing,
+/// diploid heterozygotes of the two kept alleles stay.
+fn split_call(ploidy: Ploidy, kept: [u8; 2], value: u8) -> u8 {
+    if kept.contains(&value) || (ploidy == Ploidy::Diploid && value == Base::A as u8) {
+        value
+    } else {
+        Base::N as u8
+    }
+}
+
+/// Write `chars` of one sequence, wrapping lines every 50 sites; `na` counts the sites so far.
+fn write_wrapped(ofp: &mut impl Write, chars: &[u8], na: &mut usize) -> Result<()> {
+    for &c in chars {
+        ofp.write_all(&[c])?;
+        *na += 1;
+        if (*na % 50) == 0 {
+            ofp.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+impl Converted {
+    /// Write the kept sites in LDhat `sites.txt` format.
+    pub fn write_sites(&self, ofp: &mut impl Write) -> Result<()> {
+        let (psite, nout) = self.seqs.shape();
+        writeln!(ofp, "{} {} {}", nout, psite, self.seqs.ploidy as usize)?;
+        let kept: Vec<&SiteReport> = self
+            .reports
+            .iter()
+            .filter(|r| r.filter == SiteFilter::Pass)
+            .collect();
+        for seq in self.seqs.data.iter() {
+            ofp.write_all(format!(">{}\n", seq.name()).as_bytes())?;
+            let chars: Vec<u8> = seq
+                .u8()?
+                .into_iter()
+                .zip(&kept)
+                .map(|(value, report)| {
+                    let base = Base::from(value.unwrap_or(Base::N as u8));
+                    site_char(self.seqs.ploidy, report, base)
+                })
+                .collect();
+            let mut na = 0;
+            write_wrapped(ofp, &chars, &mut na)?;
+            if (na % 50) != 0 {
+                ofp.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the positions of the kept sites in LDhat `locs.txt` format.
+    pub fn write_locs(&self, loc: &mut impl Write) -> Result<()> {
+        write!(
+            loc,
+            "{} {} {}",
+            self.locs.data.len(),
+            self.locs.length,
+            self.locs.model
+        )?;
+        for position in &self.locs.data {
+            write!(loc, "\n{:.3}", position)?;
+        }
+        Ok(())
+    }
+
+    /// The kept sites `start..end` alone, with the input-site reports restricted to them.
+    pub fn window(&self, start: usize, end: usize) -> Converted {
+        Converted {
+            seqs: Seqs {
+                ploidy: self.seqs.ploidy,
+                data: self.seqs.data.slice(start as i64, end - start),
+            },
+            locs: Locs {
+                data: self.locs.data[start..end].to_vec(),
+                length: self.locs.length,
+                model: self.locs.model,
+                contig: self.locs.contig.clone(),
+            },
+            counts: self.counts.clone(),
+            reports: self
+                .reports
+                .iter()
+                .filter(|r| r.filter == SiteFilter::Pass)
+                .skip(start)
+                .take(end - start)
+                .cloned()
+                .collect(),
+            samples: self.samples.clone(),
+        }
+    }
+
+    /// Ranges of `size` kept sites, consecutive ones sharing `overlap` sites.
+    pub fn windows(&self, size: usize, overlap: usize) -> Result<Vec<(usize, usize)>> {
+        if size == 0 || overlap >= size {
+            return Err(anyhow::anyhow!(
+                "Window overlap {} must be smaller than its size {}",
+                overlap,
+                size
+            ));
+        }
+        let psite = self.seqs.len();
+        let mut ranges = vec![];
+        let mut start = 0;
+        loop {
+            let end = core::cmp::min(start + size, psite);
+            ranges.push((start, end));
+            if end == psite {
+                break;
+            }
+            start += size - overlap;
+        }
+        Ok(ranges)
+    }
+
+    /// Per-site filter decisions as a table, one row per input site.
+    pub fn site_table(&self) -> Result<DataFrame> {
+        site_table(&self.reports, 0)
+    }
+
+    /// Write every dropped sample and site with the reason, as tab-separated
+    /// `kind`, `id`, `position`, `missing` and `filter` columns.
+    pub fn write_filter_report(&self, ofp: &mut impl Write) -> Result<()> {
+        write_sample_filters(ofp, &self.samples)?;
+        write_site_filters(ofp, &self.reports, 0)
+    }
+
+    /// Per-sample filter decisions as a table, one row per input sample.
+    pub fn sample_table(&self) -> Result<DataFrame> {
+        sample_table(&self.samples)
+    }
+}
+
+/// Filter decisions of consecutive sites as a table, the first being input site `offset + 1`.
+fn site_table(reports: &[SiteReport], offset: usize) -> Result<DataFrame> {
+    let minor: Vec<String> = reports
+        .iter()
+        .map(|r| Base::from(r.minor).to_string())
+        .collect();
+    Ok(df!(
+        "site" => (offset as u32 + 1..=(offset + reports.len()) as u32).collect::<Vec<_>>(),
+        "position" => reports.iter().map(|r| r.position).collect::<Vec<_>>(),
+        "missing" => reports.iter().map(|r| r.missing).collect::<Vec<_>>(),
+        "alleles" => reports.iter().map(|r| r.alleles).collect::<Vec<_>>(),
+        "minor" => minor,
+        "split" => reports.iter().map(|r| r.split.is_some()).collect::<Vec<_>>(),
+        "filter" => reports.iter().map(|r| r.filter.to_string()).collect::<Vec<_>>(),
+    )?)
+}
+
+/// Filter decisions of samples as a table.
+fn sample_table(samples: &[SampleReport]) -> Result<DataFrame> {
+    Ok(df!(
+        "sample" => samples.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(),
+        "missing" => samples.iter().map(|r| r.missing).collect::<Vec<_>>(),
+        "filter" => samples.iter().map(|r| r.filter.to_string()).collect::<Vec<_>>(),
+    )?)
+}
+
+/// Write the header of the filter report and its rows for the dropped `samples`.
+fn write_sample_filters(ofp: &mut impl Write, samples: &[SampleReport]) -> Result<()> {
+    writeln!(ofp, "kind\tid\tposition\tmissing\tfilter")?;
+    for sample in samples.iter().filter(|s| s.filter != SampleFilter::Pass) {
+        writeln!(
+            ofp,
+            "sample\t{}\t.\t{}\t{}",
+            sample.name, sample.missing, sample.filter
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the filter report rows of the dropped sites among consecutive ones, the first
+/// being input site `offset + 1`.
+fn write_site_filters(ofp: &mut impl Write, reports: &[SiteReport], offset: usize) -> Result<()> {
+    for (i, report) in reports.iter().enumerate() {
+        if report.filter != SiteFilter::Pass {
+            writeln!(
+                ofp,
+                "site\t{}\t{}\t{}\t{}",
+                offset + i + 1,
+                report.position,
+                report.missing,
+                report.filter
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Counts of the `T`, `C` and `A` genotypes (`0`, `1` and `2`) of diploid sites,
+/// from their call counts.
+fn genotype_counts(calls: &[[u32; 5]]) -> Vec<[u32; 3]> {
+    calls.iter().map(|&[_, t, c, a, _]| [t, c, a]).collect()
+}
+
+/// Two-sided Hardy–Weinberg exact test of Wigginton, Cutler and Abecasis (2005).
+pub fn hwe_exact(hets: u32, hom1: u32, hom2: u32) -> f64 {
+    let homr = hom1.min(hom2) as usize;
+    let homc = hom1.max(hom2) as usize;
+    let hets = hets as usize;
+    let n = hets + homr + homc;
+    if n == 0 {
+        return 1.;
+    }
+    let rare = 2 * homr + hets;
+    let mut probs = vec![0f64; rare + 1];
+    // Start from the most likely heterozygote count, which has the parity of `rare`
+    let mut mid = rare * (2 * n - rare) / (2 * n);
+    if mid % 2 != rare % 2 {
+        mid += 1;
+    }
+    probs[mid] = 1.;
+    let (mut h, mut r, mut c) = (mid, (rare - mid) / 2, n - mid - (rare - mid) / 2);
+    while h >= 2 {
+        probs[h - 2] = probs[h] * (h * (h - 1)) as f64 / (4 * (r + 1) * (c + 1)) as f64;
+        h -= 2;
+        r += 1;
+        c += 1;
+    }
+    let (mut h, mut r, mut c) = (mid, (rare - mid) / 2, n - mid - (rare - mid) / 2);
+    while h + 2 <= rare {
+        probs[h + 2] = probs[h] * (4 * r * c) as f64 / ((h + 2) * (h + 1)) as f64;
+        h += 2;
+        r -= 1;
+        c -= 1;
+    }
+    let sum: f64 = probs.iter().sum();
+    let p: f64 = probs.iter().filter(|&&p| p <= probs[hets]).sum();
+    (p / sum).min(1.)
+}
+
+#[test]
+fn test_hwe_exact() {
+    assert!((hwe_exact(50, 25, 25) - 1.).abs() < 1e-9);
+    assert!(hwe_exact(0, 50, 50) < 1e-20);
+    assert_eq!(hwe_exact(0, 0, 0), 1.);
+    // Exact enumeration of the null distribution in Wigginton et al. (2005)
+    assert!((hwe_exact(57, 14, 29) - 0.150680).abs() < 1e-6);
+    assert!((hwe_exact(40, 30, 30) - 0.046855).abs() < 1e-6);
+    assert!((hwe_exact(20, 40, 40) / 7.901338e-10 - 1.).abs() < 1e-6);
+}
+
+/// Per-site filter decisions, shared by [`convert`] and [`convert_chunked`].
+struct SiteFilters<'a> {
+    options: &'a ConvertOptions,
+    lower: usize,
+    upper: usize,
+    /// 2 if only biallelic sites are kept, 1 otherwise
+    fl: u32,
+    /// Number of sampled chromosomes
+    total: f64,
+    ploidy: Ploidy,
+    last_kept: Option<f64>,
+}
+
+impl<'a> SiteFilters<'a> {
+    fn new(options: &'a ConvertOptions, lseq: usize, total: f64, ploidy: Ploidy) -> Self {
+        if options.hwe.is_some() && ploidy == Ploidy::Haploid {
+            log::warn!("Hardy–Weinberg test skipped, it only applies to diploid data");
+        }
+        let (lower, upper) = options.sites.unwrap_or((0, lseq));
+        let fl = if options.only2 || options.freqcut > 0. {
+            2
+        } else {
+            1
+        };
+        Self {
+            options,
+            lower,
+            upper,
+            fl,
+            total,
+            ploidy,
+            last_kept: None,
+        }
+    }
+
+    /// Decide site `i` from its `N`, `T`, `C`, `A`, `G` counts, and from its diploid
+    /// genotype counts when testing Hardy–Weinberg equilibrium.
+    /// Sites are independent here, the minimum spacing is applied by [`Self::space`].
+    fn report(
+        &self,
+        i: usize,
+        position: f64,
+        counts: ndarray::ArrayView1<u32>,
+        genotypes: Option<[u32; 3]>,
+    ) -> Result<SiteReport> {
+        let options = self.options;
+        let mut row = counts.slice(ndarray::s![1usize..]).to_owned();
+        let mut missing = counts[0];
+        let mut split = None;
+        if options.split_multiallelic && row.iter().filter(|&&x| x > 0).count() > 2 {
+            let mut order: Vec<usize> = (0..row.len()).collect();
+            // Diploid genotypes `0` and `1` already carry the two most common alleles, and
+            // `3` the others, see `Seqs`
+            if self.ploidy == Ploidy::Haploid {
+                // Stable sort, so ties keep the T, C, A, G order
+                order.sort_by_key(|&k| std::cmp::Reverse(row[k]));
+            }
+            for &k in &order[2..] {
+                missing += row[k];
+                row[k] = 0;
+            }
+            split = Some([
+                order[0] as u8 + Base::T as u8,
+                order[1] as u8 + Base::T as u8,
+            ]);
+        }
+        let na = row.mapv(|x| (x > 0) as u32).sum();
+        let minor = if na > 0 {
+            row.mapv(|x| if x > 0 { x as f32 } else { f32::NAN })
+                .argmin_skipnan()? as u8
+                + Base::T as u8
+        } else {
+            Base::N as u8
+        };
+        let total = self.total;
+        let filter = if i < self.lower || i >= self.upper {
+            SiteFilter::OutOfRange
+        } else if !options.in_region(position) {
+            SiteFilter::OutOfRegion
+        } else if na < 2 {
+            SiteFilter::Monomorphic
+        } else if self.fl == 1 {
+            SiteFilter::Pass
+        } else if na > 2 {
+            SiteFilter::NotBiallelic
+        } else {
+            let nmin = row[(minor - Base::T as u8) as usize] as f64;
+            if nmin == total || nmin <= total * options.freqcut {
+                SiteFilter::MinorFrequency
+            } else if missing as f64 > total * options.missfreqcut {
+                SiteFilter::Missing
+            } else {
+                SiteFilter::Pass
+            }
+        };
+        let filter = match (filter, genotypes, options.hwe) {
+            (SiteFilter::Pass, Some([hom1, hom2, hets]), Some(pcut)) if na == 2 => {
+                if hwe_exact(hets, hom1, hom2) < pcut {
+                    SiteFilter::HardyWeinberg
+                } else {
+                    SiteFilter::Pass
+                }
+            }
+            _ => filter,
+        };
+        Ok(SiteReport {
+            position,
+            missing,
+            alleles: na,
+            minor,
+            split,
+            filter,
+        })
+    }
+
+    /// Drop passing site `i` if it is closer than the minimum spacing to the last kept one.
+    /// Sites must be given in order.
+    fn space(&mut self, i: usize, report: &mut SiteReport) {
+        match self.last_kept {
+            Some(last)
+                if report.filter == SiteFilter::Pass
+                    && report.position - last < self.options.min_spacing =>
+            {
+                report.filter = SiteFilter::Spacing
+            }
+            _ => {}
+        }
+        if report.filter == SiteFilter::Pass {
+            self.last_kept = Some(report.position);
+        } else {
+            log::debug!("Site {} dropped: {}", i + 1, report.filter);
+        }
+    }
+}
+
+/// Filter sites and subsample sequences without touching the filesystem.
+///
+/// At most [`SEQ_MAX`] sequences are considered, `options.nout` of which are drawn with `rng`.
+pub fn convert(
+    seqs: &Seqs,
+    locs: &Locs,
+    options: &ConvertOptions,
+    rng: &mut impl Rng,
+) -> Result<Converted> {
+    let mut samples = Vec::with_capacity(seqs.names().len());
+    for (j, column) in seqs.data.iter().enumerate() {
+        let missing = seqs.missing_count(j)?;
+        let filter = if missing as f64 > seqs.len() as f64 * options.sample_missfreqcut {
+            log::info!(
+                "Sample {} dropped: {} missing genotypes",
+                column.name(),
+                missing
+            );
+            SampleFilter::Missing
+        } else {
+            SampleFilter::Pass
+        };
+        samples.push(SampleReport {
+            name: column.name().to_string(),
+            missing,
+            filter,
+        });
+    }
+    let seqs = &seqs.filter_samples(|name| {
+        samples
+            .iter()
+            .any(|r| r.name == name && r.filter == SampleFilter::Pass)
+    })?;
+    if seqs.names().is_empty() {
+        return Err(anyhow::anyhow!("No samples pass --sample-missfreqcut"));
+    }
+    let (lseq, mut nseq) = seqs.shape();
+    if locs.data.len() != lseq {
+        return Err(anyhow::anyhow!(
+            "{} positions in locs but {} sites in seqs",
+            locs.data.len(),
+            lseq
+        ));
+    }
+    if nseq > SEQ_MAX {
+        log::warn!(
+            "More than max no. sequences: Using first {} for analysis",
+            SEQ_MAX
+        );
+        nseq = SEQ_MAX;
+    }
+    let nout = core::cmp::min(options.nout.unwrap_or(nseq), nseq);
+    let mut index = rand::seq::index::sample(rng, nseq, nout).into_vec();
+    index.sort();
+    let calls = seqs.call_counts()?;
+    let counts = allele_table(&calls, seqs.ploidy)?;
+    let nall = counts.to_ndarray::<UInt32Type>()?;
+    let total = (nseq * seqs.ploidy as usize) as f64;
+    let genotypes = match (options.hwe, seqs.ploidy) {
+        (Some(_), Ploidy::Diploid) => Some(genotype_counts(&calls)),
+        _ => None,
+    };
+    let mut filters = SiteFilters::new(options, lseq, total, seqs.ploidy);
+    let mut reports = (0..lseq)
+        .into_par_iter()
+        .map(|i| {
+            let genotypes = genotypes.as_ref().map(|g| g[i]);
+            filters.report(i, locs.data[i], nall.row(i), genotypes)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (i, report) in reports.iter_mut().enumerate() {
+        filters.space(i, report);
+    }
+    let mask: Vec<bool> = reports
+        .iter()
+        .map(|r| r.filter == SiteFilter::Pass)
+        .collect();
+    if reports.iter().all(|r| r.filter == SiteFilter::OutOfRegion) {
+        return Err(anyhow::anyhow!(
+            "No site lies in the selected region, check that the BED intervals (in bp) and \
+             --region are on the scale of the locs"
+        ));
+    }
+    if !mask.contains(&true) {
+        return Err(anyhow::anyhow!("No data to output"));
+    }
+    let mask = BooleanChunked::from_slice("mask", &mask);
+    let splits: Vec<Option<[u8; 2]>> = reports
+        .iter()
+        .filter(|r| r.filter == SiteFilter::Pass)
+        .map(|r| r.split)
+        .collect();
+    let mut columns = Vec::with_capacity(index.len());
+    for &i in &index {
+        let column = seqs.data.select_at_idx(i).unwrap().filter(&mask)?;
+        if splits.iter().all(Option::is_none) {
+            columns.push(column);
+            continue;
+        }
+        let values: Vec<u8> = column
+            .u8()?
+            .into_iter()
+            .zip(&splits)
+            .map(|(v, split)| match (v.unwrap_or(Base::N as u8), split) {
+                (v, Some(kept)) => split_call(seqs.ploidy, *kept, v),
+                (v, None) => v,
+            })
+            .collect();
+        columns.push(Series::new(column.name(), values));
+    }
+    let data = DataFrame::new(columns)?;
+    Ok(Converted {
+        seqs: Seqs {
+            ploidy: seqs.ploidy,
+            data,
+        },
+        locs: Locs {
+            data: reports
+                .iter()
+                .filter(|r| r.filter == SiteFilter::Pass)
+                .map(|r| r.position)
+                .collect(),
+            length: locs.length,
+            model: locs.model,
+            contig: locs.contig.clone(),
+        },
+        counts,
+        reports,
+        samples,
+    })
+}
+
+#[test]
+fn test_convert() {
+    use rand::SeedableRng;
+    let seqs = Seqs {
+        ploidy: Ploidy::Haploid,
+        data: df!(
+            "SampleA" => &[2u8, 3, 3, 5, 3],
+            "SampleB" => &[2u8, 4, 3, 5, 3],
+            "SampleC" => &[2u8, 3, 1, 3, 4],
+            "SampleD" => &[2u8, 3, 3, 4, 3],
+        )
+        .unwrap(),
+    };
+    let locs = Locs::new_from_length(5);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let options = ConvertOptions {
+        only2: true,
+        ..Default::default()
+    };
+    let converted = convert(&seqs, &locs, &options, &mut rng).unwrap();
+    let filters: Vec<_> = converted.reports.iter().map(|r| r.filter).collect();
+    assert_eq!(
+        filters,
+        vec![
+            SiteFilter::Monomorphic,
+            SiteFilter::Pass,
+            SiteFilter::Monomorphic,
+            SiteFilter::NotBiallelic,
+            SiteFilter::Pass,
+        ]
+    );
+    assert_eq!(converted.seqs.shape(), (2, 4));
+    assert_eq!(converted.locs.data, vec![2., 5.]);
+    let mut sites = vec![];
+    converted.write_sites(&mut sites).unwrap();
+    assert_eq!(
+        String::from_utf8(sites).unwrap(),
+        "4 2 1\n>SampleA\n00\n>SampleB\n10\n>SampleC\n01\n>SampleD\n00\n"
+    );
+    assert_eq!(converted.windows(1, 0).unwrap(), vec![(0, 1), (1, 2)]);
+    assert_eq!(converted.window(1, 2).locs.data, vec![5.]);
+
+    let options = ConvertOptions {
+        exclude: vec![(1., 2.)],
+        ..Default::default()
+    };
+    let converted = convert(&seqs, &locs, &options, &mut rng).unwrap();
+    assert_eq!(converted.reports[1].filter, SiteFilter::OutOfRegion);
+    assert_eq!(converted.locs.data, vec![4., 5.]);
+
+    let options = ConvertOptions {
+        min_spacing: 3.,
+        sample_missfreqcut: 0.,
+        ..Default::default()
+    };
+    let converted = convert(&seqs, &locs, &options, &mut rng).unwrap();
+    assert_eq!(converted.samples[2].filter, SampleFilter::Missing);
+    assert_eq!(converted.reports[3].filter, SiteFilter::Spacing);
+    let mut report = vec![];
+    converted.write_filter_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.starts_with("kind\tid\tposition\tmissing\tfilter\nsample\tSampleC\t"));
+    assert!(report.contains("\tspacing\n"));
+
+    let options = ConvertOptions {
+        only2: true,
+        split_multiallelic: true,
+        ..Default::default()
+    };
+    let converted = convert(&seqs, &locs, &options, &mut rng).unwrap();
+    assert_eq!(
+        converted.reports[3].split,
+        Some([Base::G as u8, Base::C as u8])
+    );
+    assert_eq!(converted.reports[3].missing, 1);
+    assert!(converted.seqs["SampleD"].series_equal(&Series::new("SampleD", [3u8, 1, 3])));
+
+    let seqs = Seqs {
+        ploidy: Ploidy::Diploid,
+        data: df!(
+            "SampleA" => &[2u8, 2],
+            "SampleB" => &[4u8, 3],
+            "SampleC" => &[5u8, 4],
+        )
+        .unwrap(),
+    };
+    let locs = Locs::new_from_length(2);
+    let options = ConvertOptions {
+        only2: true,
+        ..Default::default()
+    };
+    let converted = convert(&seqs, &locs, &options, &mut rng).unwrap();
+    assert_eq!(converted.reports[0].filter, SiteFilter::NotBiallelic);
+    let options = ConvertOptions {
+        only2: true,
+        split_multiallelic: true,
+        ..Default::default()
+    };
+    let converted = convert(&seqs, &locs, &options, &mut rng).unwrap();
+    assert_eq!(
+        converted.reports[0].split,
+        Some([Base::T as u8, Base::C as u8])
+    );
+    assert_eq!(converted.reports[0].missing, 2);
+    assert!(converted.seqs["SampleB"].series_equal(&Series::new("SampleB", [4u8, 3])));
+    assert!(converted.seqs["SampleC"].series_equal(&Series::new("SampleC", [1u8, 4])));
+}
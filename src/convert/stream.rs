@@ -0,0 +1,317 @@
+use super::{
+    genotype_counts, sample_table, site_char, site_table, write_sample_filters, write_site_filters,
+    write_wrapped, ConvertOptions, SampleFilter, SampleReport, SiteFilter, SiteFilters, SiteReport,
+};
+use crate::{
+    io::{
+        allele_table, create, output_path, write_freq_rows, Compress, Genotypes, Locs, Ploidy,
+        SitesIndex,
+    },
+    LDhatResult as Result, SEQ_MAX,
+};
+use polars::prelude::*;
+use rand::Rng;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Ranges of at most `chunk_size` sites covering `0..lseq`.
+fn chunks(lseq: usize, chunk_size: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..lseq)
+        .step_by(chunk_size.max(1))
+        .map(move |start| (start, (start + chunk_size.max(1)).min(lseq)))
+}
+
+/// Append `df` to a tab-separated table, with the header line before the first rows only.
+fn append_tsv(ofp: &mut impl Write, df: &mut DataFrame, header: bool) -> Result<()> {
+    CsvWriter::new(ofp)
+        .has_header(header)
+        .with_delimiter(b'\t')
+        .finish(df)?;
+    Ok(())
+}
+
+/// Where and how [`convert_chunked`] writes its outputs.
+pub struct ChunkedOutput<'a> {
+    /// Prepended to every output file name
+    pub prefix: &'a str,
+    /// Compression of the LDhat text files and the filter report
+    pub compress: Option<Compress>,
+    /// Also write the `site_filter.tsv` and `freqs.tsv` tables
+    pub tables: bool,
+}
+
+/// Result of [`convert_chunked`].
+pub struct Chunked {
+    /// Paths of the files written
+    pub outputs: Vec<PathBuf>,
+    /// Path of the `locs.txt` written, among `outputs`
+    pub locs: PathBuf,
+    /// One report per input sample, if their missing genotypes were counted: with
+    /// `--sample-missfreqcut` below 1 or [`ChunkedOutput::tables`]
+    pub samples: Vec<SampleReport>,
+    /// Number of sequences considered, at most [`SEQ_MAX`]
+    pub nseq: usize,
+    /// Number of sequences written
+    pub nout: usize,
+    /// Number of sites written
+    pub psite: usize,
+}
+
+impl Chunked {
+    /// Per-sample filter decisions as a table, one row per input sample.
+    pub fn sample_table(&self) -> Result<DataFrame> {
+        sample_table(&self.samples)
+    }
+}
+
+/// Streaming counterpart of [`convert`](super::convert) for sites files too large for memory.
+///
+/// The sequences of `index` are read `chunk_size` sites at a time, so peak memory is bounded
+/// by the chunk rather than the alignment. Each chunk is held as a [`GenotypeMatrix`], whose
+/// popcounts give the allele counts. Allele counts and filter decisions are made per
+/// chunk and `freqs.txt`, `filter_report.tsv`, `sites.txt` and `locs.txt` are written as
+/// they go, with the site tables if asked; the kept sites are spooled to `sites.txt.tmp`
+/// in chunk order and reassembled per sequence at the end.
+///
+/// [`GenotypeMatrix`]: crate::io::GenotypeMatrix
+pub fn convert_chunked(
+    index: &SitesIndex,
+    locs: &Locs,
+    options: &ConvertOptions,
+    chunk_size: usize,
+    output: &ChunkedOutput,
+    rng: &mut impl Rng,
+) -> Result<Chunked> {
+    let (prefix, compress) = (output.prefix, output.compress);
+    let lseq = index.lseq;
+    if locs.data.len() != lseq {
+        return Err(anyhow::anyhow!(
+            "{} positions in locs but {} sites in seqs",
+            locs.data.len(),
+            lseq
+        ));
+    }
+    let mut index = index.clone();
+    let mut samples = vec![];
+    if options.sample_missfreqcut < 1. || output.tables {
+        let mut missing = vec![0u32; index.names().len()];
+        for (start, end) in chunks(lseq, chunk_size) {
+            let chunk = index.read_matrix(start, end)?;
+            for (j, m) in missing.iter_mut().enumerate() {
+                *m += chunk.missing_count(j)?;
+            }
+        }
+        for (name, &missing) in index.names().into_iter().zip(&missing) {
+            let filter = if missing as f64 > lseq as f64 * options.sample_missfreqcut {
+                log::info!("Sample {} dropped: {} missing genotypes", name, missing);
+                SampleFilter::Missing
+            } else {
+                SampleFilter::Pass
+            };
+            samples.push(SampleReport {
+                name: name.to_string(),
+                missing,
+                filter,
+            });
+        }
+        index.retain(|name| {
+            samples
+                .iter()
+                .any(|s| s.name == name && s.filter == SampleFilter::Pass)
+        });
+    }
+    let mut nseq = index.names().len();
+    if nseq == 0 {
+        return Err(anyhow::anyhow!("No samples pass --sample-missfreqcut"));
+    }
+    if nseq > SEQ_MAX {
+        log::warn!(
+            "More than max no. sequences: Using first {} for analysis",
+            SEQ_MAX
+        );
+        nseq = SEQ_MAX;
+    }
+    let nout = core::cmp::min(options.nout.unwrap_or(nseq), nseq);
+    let mut sampled = rand::seq::index::sample(rng, nseq, nout).into_vec();
+    sampled.sort();
+    let total = (nseq * index.ploidy as usize) as f64;
+    let mut filters = SiteFilters::new(options, lseq, total, index.ploidy);
+
+    let freqs_path = output_path(format!("{}freqs.txt", prefix), compress);
+    let sites_path = output_path(format!("{}sites.txt", prefix), compress);
+    let locs_path = output_path(format!("{}locs.txt", prefix), compress);
+    let report_path = output_path(format!("{}filter_report.tsv", prefix), compress);
+    let spool_path = PathBuf::from(format!("{}sites.txt.tmp", prefix));
+    let mut freqs = create(&freqs_path)?;
+    freqs.write_all("\nAllele frequencies\n\n Site   -   T/0  C/1  A/2  G/3\n\n".as_bytes())?;
+    let mut filter_report = create(&report_path)?;
+    write_sample_filters(&mut filter_report, &samples)?;
+    let table_paths = [
+        PathBuf::from(format!("{}site_filter.tsv", prefix)),
+        PathBuf::from(format!("{}freqs.tsv", prefix)),
+    ];
+    let mut tables = if output.tables {
+        Some((create(&table_paths[0])?, create(&table_paths[1])?))
+    } else {
+        None
+    };
+    let mut spool = BufWriter::new(File::create(&spool_path)?);
+    // Number of kept sites of every chunk, to find a sequence's sites in the spool
+    let mut kept_per_chunk = vec![];
+    let mut kept: Vec<SiteReport> = vec![];
+    let mut out_of_region = 0;
+    for (start, end) in chunks(lseq, chunk_size) {
+        let chunk = index.read_matrix(start, end)?;
+        let calls = chunk.call_counts()?;
+        let mut counts = allele_table(&calls, index.ploidy)?;
+        write_freq_rows(&counts, start, &mut freqs)?;
+        let nall = counts.to_ndarray::<UInt32Type>()?;
+        let genotypes = match (options.hwe, index.ploidy) {
+            (Some(_), Ploidy::Diploid) => Some(genotype_counts(&calls)),
+            _ => None,
+        };
+        let mut reports = (0..end - start)
+            .into_par_iter()
+            .map(|k| {
+                let genotypes = genotypes.as_ref().map(|g| g[k]);
+                filters.report(start + k, locs.data[start + k], nall.row(k), genotypes)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for (k, report) in reports.iter_mut().enumerate() {
+            filters.space(start + k, report);
+        }
+        write_site_filters(&mut filter_report, &reports, start)?;
+        if let Some((site_filter, freqs_table)) = tables.as_mut() {
+            append_tsv(site_filter, &mut site_table(&reports, start)?, start == 0)?;
+            let site = Series::new("site", (start as u32 + 1..=end as u32).collect::<Vec<_>>());
+            append_tsv(freqs_table, counts.insert_at_idx(0, site)?, start == 0)?;
+        }
+        out_of_region += reports
+            .iter()
+            .filter(|report| report.filter == SiteFilter::OutOfRegion)
+            .count();
+        let reports: Vec<_> = reports
+            .into_iter()
+            .enumerate()
+            .filter(|(_, report)| report.filter == SiteFilter::Pass)
+            .collect();
+        for &j in &sampled {
+            let chars: Vec<u8> = reports
+                .iter()
+                .map(|(k, report)| site_char(index.ploidy, report, chunk.get(*k, j)))
+                .collect();
+            spool.write_all(&chars)?;
+        }
+        kept_per_chunk.push(reports.len());
+        kept.extend(reports.into_iter().map(|(_, report)| report));
+    }
+    spool.flush()?;
+    drop(spool);
+    freqs.finish()?;
+    filter_report.finish()?;
+    if let Some((site_filter, freqs_table)) = tables {
+        site_filter.finish()?;
+        freqs_table.finish()?;
+    }
+    if kept.is_empty() {
+        std::fs::remove_file(&spool_path)?;
+        if out_of_region == lseq {
+            return Err(anyhow::anyhow!(
+                "No site lies in the selected region, check that the BED intervals (in bp) and \
+                 --region are on the scale of the locs"
+            ));
+        }
+        return Err(anyhow::anyhow!("No data to output"));
+    }
+
+    let mut loc = create(&locs_path)?;
+    write!(loc, "{} {} {}", kept.len(), locs.length, locs.model)?;
+    for report in &kept {
+        write!(loc, "\n{:.3}", report.position)?;
+    }
+    loc.finish()?;
+
+    let names = index.names();
+    let mut sites = create(&sites_path)?;
+    writeln!(sites, "{} {} {}", nout, kept.len(), index.ploidy as usize)?;
+    let mut spool = File::open(&spool_path)?;
+    for (s, &j) in sampled.iter().enumerate() {
+        sites.write_all(format!(">{}\n", names[j]).as_bytes())?;
+        let mut na = 0;
+        let mut chunk_offset = 0u64;
+        for &n in &kept_per_chunk {
+            let mut chars = vec![0u8; n];
+            spool.seek(SeekFrom::Start(chunk_offset + (s * n) as u64))?;
+            spool.read_exact(&mut chars)?;
+            write_wrapped(&mut sites, &chars, &mut na)?;
+            chunk_offset += (nout * n) as u64;
+        }
+        if (na % 50) != 0 {
+            sites.write_all(b"\n")?;
+        }
+    }
+    sites.finish()?;
+    std::fs::remove_file(&spool_path)?;
+    log::info!(
+        "{} of {} sites written for {} sequences",
+        kept.len(),
+        lseq,
+        nout
+    );
+    let mut outputs = vec![sites_path, locs_path.clone(), freqs_path, report_path];
+    if output.tables {
+        outputs.extend(table_paths);
+    }
+    Ok(Chunked {
+        outputs,
+        locs: locs_path,
+        samples,
+        nseq,
+        nout,
+        psite: kept.len(),
+    })
+}
+
+#[test]
+fn test_convert_chunked() {
+    use crate::{convert::convert, io::read_sites};
+    use rand::SeedableRng;
+    let dir = std::env::temp_dir();
+    let path = dir.join("ldhat_test_chunked_input.txt");
+    std::fs::write(
+        &path,
+        "4 5 1\n>SampleA\nTCC\nGC\n>SampleB\nTAC\nGC\n>SampleC\nTCN\nCA\n>SampleD\nTCC\nAC\n",
+    )
+    .unwrap();
+    let index = SitesIndex::build(&path).unwrap();
+    let locs = Locs::new_from_length(5);
+    let prefix = format!("{}/ldhat_test_chunked_", dir.display());
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let output = ChunkedOutput {
+        prefix: &prefix,
+        compress: None,
+        tables: true,
+    };
+    let chunked =
+        convert_chunked(&index, &locs, &Default::default(), 2, &output, &mut rng).unwrap();
+    assert_eq!(chunked.outputs.len(), 6);
+    assert_eq!(chunked.samples.len(), 4);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let seqs = read_sites(&path).unwrap();
+    let converted = convert(&seqs, &locs, &Default::default(), &mut rng).unwrap();
+    let mut expected = vec![];
+    converted.write_sites(&mut expected).unwrap();
+    let written = std::fs::read(format!("{}sites.txt", prefix)).unwrap();
+    assert_eq!(written, expected);
+    assert!(!PathBuf::from(format!("{}sites.txt.tmp", prefix)).exists());
+    let mut expected = vec![];
+    converted.write_filter_report(&mut expected).unwrap();
+    let written = std::fs::read(format!("{}filter_report.tsv", prefix)).unwrap();
+    assert_eq!(written, expected);
+    let mut expected = vec![];
+    append_tsv(&mut expected, &mut converted.site_table().unwrap(), true).unwrap();
+    let written = std::fs::read(format!("{}site_filter.tsv", prefix)).unwrap();
+    assert_eq!(written, expected);
+}
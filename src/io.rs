@@ -1,15 +1,161 @@
 use crate::LDhatResult as Result;
 use bio::io::fasta;
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
 use polars::prelude::*;
+use rayon::prelude::*;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, PartialEq)]
+pub mod index;
+pub mod matrix;
+pub mod ms;
+pub mod nexus;
+pub mod phylip;
+pub mod plink;
+
+pub use index::SitesIndex;
+pub use matrix::GenotypeMatrix;
+pub use ms::read_ms;
+pub use nexus::read_nexus;
+pub use phylip::read_phylip;
+pub use plink::read_plink;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Open a file for reading.
+/// Gzip (including bgzip) and zstd compression is detected from magic bytes
+/// and decompressed on the fly.
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let magic = reader.fill_buf()?;
+    let is_gzip = magic.starts_with(&GZIP_MAGIC);
+    let is_zstd = magic.starts_with(&ZSTD_MAGIC);
+    if is_gzip {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else if is_zstd {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(
+            reader,
+        )?)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Compression of the text files written.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Compress {
+    /// gzip
+    Gz,
+    /// Zstandard
+    Zst,
+}
+
+/// `name`, with the extension that makes [`create`] compress it when `compress` is set.
+pub fn output_path(name: String, compress: Option<Compress>) -> PathBuf {
+    match compress {
+        Some(Compress::Gz) => PathBuf::from(name + ".gz"),
+        Some(Compress::Zst) => PathBuf::from(name + ".zst"),
+        None => PathBuf::from(name),
+    }
+}
+
+enum Writer {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Writer {
+    fn finish(self) -> Result<()> {
+        let mut file = match self {
+            Writer::Plain(file) => file,
+            Writer::Gzip(encoder) => encoder.finish()?,
+            Writer::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// File being written by [`create`].
+///
+/// Call [`Output::finish`] once done: dropping it also finishes the file, but cannot report
+/// errors such as a full disk.
+pub struct Output(Option<Writer>);
+
+impl Output {
+    pub fn finish(mut self) -> Result<()> {
+        self.0.take().map_or(Ok(()), Writer::finish)
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self.0.as_mut().expect("output already finished") {
+            Writer::Plain(file) => file,
+            Writer::Gzip(encoder) => encoder,
+            Writer::Zstd(encoder) => encoder,
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        if let Some(writer) = self.0.take() {
+            let _ = writer.finish();
+        }
+    }
+}
+
+/// Create a file for writing, compressed if its name ends in `.gz` or `.zst`.
+pub fn create(path: &Path) -> Result<Output> {
+    let file = BufWriter::new(File::create(path)?);
+    let writer = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Writer::Gzip(GzEncoder::new(file, Compression::default())),
+        Some("zst") => Writer::Zstd(zstd::Encoder::new(file, 0)?),
+        _ => Writer::Plain(file),
+    };
+    Ok(Output(Some(writer)))
+}
+
+/// Create `path`, fill it with `write` and finish it.
+pub fn write_file(path: &Path, write: impl FnOnce(&mut Output) -> Result<()>) -> Result<()> {
+    let mut ofp = create(path)?;
+    write(&mut ofp)?;
+    ofp.finish()
+}
+
+#[test]
+fn test_compressed_roundtrip() {
+    for name in ["ldhat_test_roundtrip.gz", "ldhat_test_roundtrip.zst"] {
+        let path = std::env::temp_dir().join(name);
+        let mut output = create(&path).unwrap();
+        output.write_all(b"4 10 1\n").unwrap();
+        output.finish().unwrap();
+        let mut content = String::new();
+        open(&path).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "4 10 1\n");
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Locs {
     pub data: Vec<f64>,
     pub length: f64,
     pub model: Model,
+    /// Chromosome or contig of the positions, from PLINK input or `convert --chromosome`.
+    /// It is not written to `locs.txt`, `stitch --chromosome` records it in the map table
+    pub contig: Option<String>,
 }
 
 impl Locs {
@@ -18,6 +164,7 @@ impl Locs {
             data: (1..sites.len() + 1).map(|p| p as f64).collect(),
             length: sites.len() as f64,
             model: Model::CrossingOver,
+            contig: None,
         }
     }
     pub fn new_from_length(length: usize) -> Self {
@@ -25,12 +172,39 @@ impl Locs {
             data: (1..length + 1).map(|p| p as f64).collect(),
             length: length as f64,
             model: Model::CrossingOver,
+            contig: None,
+        }
+    }
+    /// Convert base-pair positions and length into `units`.
+    pub fn in_units(mut self, units: Units) -> Self {
+        let bp = units.bp();
+        self.data.iter_mut().for_each(|p| *p /= bp);
+        self.length /= bp;
+        self
+    }
+}
+
+/// Units of positions in `locs.txt`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Units {
+    /// Base pairs
+    Bp,
+    /// Kilobases
+    Kb,
+}
+
+impl Units {
+    /// Base pairs per unit.
+    pub fn bp(&self) -> f64 {
+        match self {
+            Units::Bp => 1.,
+            Units::Kb => 1000.,
         }
     }
 }
 
 /// Model of crossing-over or gene conversion
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize)]
 pub enum Model {
     CrossingOver,
     GeneConversion,
@@ -47,12 +221,13 @@ impl std::str::FromStr for Model {
     }
 }
 
-impl From<char> for Model {
-    fn from(value: char) -> Self {
+impl TryFrom<char> for Model {
+    type Error = crate::Error;
+    fn try_from(value: char) -> std::result::Result<Self, Self::Error> {
         match value {
-            'L' => Model::CrossingOver,
-            'C' => Model::GeneConversion,
-            _ => Err(crate::Error::new("Variant not found")).unwrap(),
+            'L' => Ok(Model::CrossingOver),
+            'C' => Ok(Model::GeneConversion),
+            _ => Err(Self::Error::new("Variant not found")),
         }
     }
 }
@@ -76,7 +251,7 @@ fn test_parse_model() {
 }
 
 pub fn read_locs(path: &PathBuf) -> Result<Locs> {
-    let mut file = File::open(path)?;
+    let mut file = open(path)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
     parse_locs(content.as_str())
@@ -107,14 +282,17 @@ fn parse_locs(content: &str) -> Result<Locs> {
             space1,
         ),
         terminated(double, space1),
-        terminated(
-            map_res(one_of("LC"), |c| Ok::<_, crate::Error>(Model::from(c))),
-            line_ending,
-        ),
+        terminated(map_res(one_of("LC"), Model::try_from), line_ending),
         separated_list0(multispace1, double),
     ))(content)
-    .unwrap();
-    assert_eq!(l, data.len());
+    .map_err(|e| anyhow::anyhow!("Malformed locs file: {}", e))?;
+    if l != data.len() {
+        return Err(anyhow::anyhow!(
+            "locs header declares {} SNPs but found {}",
+            l,
+            data.len()
+        ));
+    }
     if !is_monotonic_increasing(&data) {
         return Err(anyhow::anyhow!(
             "loc file SNPs not monotonically increasing"
@@ -124,6 +302,7 @@ fn parse_locs(content: &str) -> Result<Locs> {
         data,
         length,
         model,
+        contig: None,
     })
 }
 
@@ -137,11 +316,19 @@ fn test_parse_locs() {
         Locs {
             data: vec![1., 57., 180., 187., 223., 250., 438., 509., 878., 1034.],
             length: 1200.,
-            model: Model::CrossingOver
+            model: Model::CrossingOver,
+            contig: None,
         }
     );
+    assert!(parse_locs("3 100 L\n1 2").is_err());
+    assert!(parse_locs("2 100 X\n1 2").is_err());
 }
 
+/// Aligned sequences, one column of [`Base`] codes per sample and one row per site.
+///
+/// Diploid data hold genotypes rather than bases, in the slots the LDhat digits decode to:
+/// `T` and `C` for the two homozygotes (`0` and `1`), `A` for heterozygotes (`2`), and `G`
+/// for calls carrying a third allele (`3`), which sites with more than two alleles keep.
 pub struct Seqs {
     pub ploidy: Ploidy,
     pub data: polars::frame::DataFrame,
@@ -157,78 +344,254 @@ impl Seqs {
     pub fn len(&self) -> usize {
         self.data.height()
     }
+    /// Samples whose names satisfy `predicate`, in input order.
+    pub fn filter_samples(&self, predicate: impl Fn(&str) -> bool) -> Result<Seqs> {
+        let names: Vec<&str> = self.names().into_iter().filter(|n| predicate(n)).collect();
+        Ok(Seqs {
+            ploidy: self.ploidy,
+            data: self.data.select(names)?,
+        })
+    }
+    /// Build from named sequences of equal length, decoded with [`Base::from_ascii`].
+    /// Diploid nucleotide calls are first recoded into genotypes, see `recode_diploid`.
+    pub fn from_records(mut records: Vec<(String, Vec<u8>)>, ploidy: Ploidy) -> Result<Self> {
+        if let Some((first, seq)) = records.first() {
+            let lseq = seq.len();
+            for (name, seq) in &records {
+                if seq.len() != lseq {
+                    return Err(anyhow::anyhow!(
+                        "Sequence {} has {} sites but {} has {}",
+                        name,
+                        seq.len(),
+                        first,
+                        lseq
+                    ));
+                }
+            }
+        }
+        if ploidy == Ploidy::Diploid {
+            recode_diploid(&mut records);
+        }
+        let data = DataFrame::new(
+            records
+                .into_iter()
+                .map(|(name, seq)| {
+                    Series::new(
+                        &name,
+                        seq.iter()
+                            .map(|&x| Base::from_ascii(x) as u8)
+                            .collect::<Vec<u8>>(),
+                    )
+                })
+                .collect(),
+        )?;
+        Ok(Self { ploidy, data })
+    }
+    /// Save the genotype matrix as Parquet, loadable with [`read_parquet_sites`].
+    pub fn write_parquet(&self, path: &PathBuf) -> Result<()> {
+        crate::output::write_parquet(path, &mut self.data.clone())
+    }
     /// Count allele's frequency.
-    /// If prefix is not None, allele frequency will be write to `{prefix}freqs.txt`.
+    /// If prefix is not None, allele frequency will be write to `{prefix}freqs.txt`,
+    /// otherwise nothing is written.
     /// This output file format is for backward compatibility.
-    ///
-    /// Polars iter over rows is time consumed.
-    /// https://stackoverflow.com/questions/72440403
     pub fn allele_count(&self, prefix: Option<&str>) -> Result<DataFrame> {
-        let mut iters = self
-            .data
-            .iter()
-            .map(|s| Ok(s.u8()?.into_iter()))
-            .collect::<Result<Vec<_>>>()?;
-        let mut result = df!(
-            "N" => &[0u32;0],
-            "T" => &[0u32;0],
-            "C" => &[0u32;0],
-            "A" => &[0u32;0],
-            "G" => &[0u32;0],
-        )?;
-        let add = |n: u32| move |s: &Series| s + n;
-        let mut ofp = File::create(format!("{}freqs.txt", prefix.unwrap_or("")))?;
-        ofp.write("\nAllele frequencies\n\n Site   -   T/0  C/1  A/2  G/3\n\n".as_bytes())?;
-        for i in 0..self.data.height() {
-            let mut row = df!(
-                "N" => &[0u32],
-                "T" => &[0u32],
-                "C" => &[0u32],
-                "A" => &[0u32],
-                "G" => &[0u32],
-            )?;
-            for iter in &mut iters {
-                let value = iter.next().expect("");
-                if let Some(v) = value {
-                    let base = Base::from(v);
-                    if self.ploidy == Ploidy::Haploid {
-                        match base {
-                            Base::N => row.apply("N", add(1))?,
-                            Base::A => row.apply("A", add(1))?,
-                            Base::C => row.apply("C", add(1))?,
-                            Base::G => row.apply("G", add(1))?,
-                            Base::T => row.apply("T", add(1))?,
-                        };
-                    } else {
-                        match base {
-                            Base::N => row.apply("N", add(2))?,
-                            Base::A => row.apply("T", add(1))?,
-                            Base::C => row.apply("C", add(2))?,
-                            Base::G => row.apply("C", add(1))?,
-                            Base::T => row.apply("T", add(2))?,
-                        };
+        let result = allele_table(&self.call_counts()?, self.ploidy)?;
+        if let Some(prefix) = prefix {
+            let path = PathBuf::from(format!("{}freqs.txt", prefix));
+            write_file(&path, |ofp| write_freqs(&result, ofp))?;
+        }
+        Ok(result)
+    }
+}
+
+/// Genotype calls of samples at sites, held by a [`Seqs`] frame or a bit-packed
+/// [`GenotypeMatrix`]. `convert` counts and filters through it, so both stores give the
+/// same results.
+pub trait Genotypes: Sync {
+    fn ploidy(&self) -> Ploidy;
+    /// Number of samples with each call at every site, in `N`, `T`, `C`, `A`, `G` order.
+    fn call_counts(&self) -> Result<Vec<[u32; 5]>>;
+    /// Number of missing (`N`) calls of the `sample`-th sample.
+    fn missing_count(&self, sample: usize) -> Result<u32>;
+}
+
+impl Genotypes for Seqs {
+    fn ploidy(&self) -> Ploidy {
+        self.ploidy
+    }
+
+    /// Ranges of sites are counted in parallel, each into its own part of the result, so
+    /// memory does not grow with the number of threads and the counts do not depend on it.
+    fn call_counts(&self) -> Result<Vec<[u32; 5]>> {
+        let mut counts = vec![[0u32; 5]; self.len()];
+        counts
+            .par_chunks_mut(SITE_CHUNK)
+            .enumerate()
+            .try_for_each(|(k, chunk)| -> Result<()> {
+                for column in self.data.get_columns() {
+                    let values = column.slice((k * SITE_CHUNK) as i64, chunk.len());
+                    for (row, value) in chunk.iter_mut().zip(values.u8()?.into_iter()) {
+                        // Columns are N, T, C, A, G, the order of the `Base` codes
+                        row[Base::from(value.unwrap_or(Base::N as u8)) as usize - 1] += 1;
                     }
                 }
+                Ok(())
+            })?;
+        Ok(counts)
+    }
+
+    fn missing_count(&self, sample: usize) -> Result<u32> {
+        Ok(self.data.get_columns()[sample]
+            .u8()?
+            .into_iter()
+            .filter(|v| v.map_or(true, |v| Base::from(v) == Base::N))
+            .count() as u32)
+    }
+}
+
+/// Sites counted by one task of [`Genotypes::call_counts`] on [`Seqs`].
+const SITE_CHUNK: usize = 4096;
+
+/// Allele counts from the [`Genotypes::call_counts`] of every site, as returned by
+/// [`Seqs::allele_count`].
+pub fn allele_table(calls: &[[u32; 5]], ploidy: Ploidy) -> Result<DataFrame> {
+    let rows = calls.iter().map(|&[n, t, c, a, g]| match ploidy {
+        Ploidy::Haploid => [n, t, c, a, g],
+        // Diploid data hold genotypes, see `Seqs`; third alleles are counted under `G`
+        Ploidy::Diploid => [2 * n, 2 * t + a, 2 * c + a, 0, 2 * g],
+    });
+    let mut columns: [Vec<u32>; 5] = Default::default();
+    for row in rows {
+        for (column, count) in columns.iter_mut().zip(row) {
+            column.push(count);
+        }
+    }
+    let [n, t, c, a, g] = columns;
+    Ok(df!("N" => n, "T" => t, "C" => c, "A" => a, "G" => g)?)
+}
+
+#[test]
+fn test_call_counts() {
+    let lseq = SITE_CHUNK + 10;
+    let seqs = Seqs {
+        ploidy: Ploidy::Haploid,
+        data: df!(
+            "SampleA" => (0..lseq).map(|i| (i % 5) as u8 + 1).collect::<Vec<_>>(),
+            "SampleB" => vec![Base::T as u8; lseq],
+        )
+        .unwrap(),
+    };
+    let calls = seqs.call_counts().unwrap();
+    assert_eq!(calls.len(), lseq);
+    for (i, row) in calls.iter().enumerate() {
+        let mut expected = [0, 1, 0, 0, 0];
+        expected[i % 5] += 1;
+        assert_eq!(*row, expected);
+    }
+}
+
+/// Write allele counts returned by [`Seqs::allele_count`] in the legacy `freqs.txt` format.
+pub fn write_freqs(counts: &DataFrame, ofp: &mut impl Write) -> Result<()> {
+    ofp.write_all("\nAllele frequencies\n\n Site   -   T/0  C/1  A/2  G/3\n\n".as_bytes())?;
+    write_freq_rows(counts, 0, ofp)
+}
+
+/// Write the `freqs.txt` rows of sites numbered from `offset + 1`, without the header.
+pub fn write_freq_rows(counts: &DataFrame, offset: usize, ofp: &mut impl Write) -> Result<()> {
+    let nall = counts.to_ndarray::<UInt32Type>()?;
+    for (i, row) in nall.outer_iter().enumerate() {
+        ofp.write_all(
+            format!(
+                "{:>4}{:>5}{:>5}{:>5}{:>5}{:>5}\n",
+                offset + i + 1,
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+            )
+            .as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
+/// The two alleles of a nucleotide or IUPAC heterozygote code, `None` for anything else.
+fn iupac_alleles(code: u8) -> Option<(u8, u8)> {
+    match code.to_ascii_uppercase() {
+        b'A' => Some((b'A', b'A')),
+        b'C' => Some((b'C', b'C')),
+        b'G' => Some((b'G', b'G')),
+        b'T' => Some((b'T', b'T')),
+        b'R' => Some((b'A', b'G')),
+        b'Y' => Some((b'C', b'T')),
+        b'S' => Some((b'C', b'G')),
+        b'W' => Some((b'A', b'T')),
+        b'K' => Some((b'G', b'T')),
+        b'M' => Some((b'A', b'C')),
+        _ => None,
+    }
+}
+
+/// Recode diploid nucleotide calls, with IUPAC codes such as `R` or `Y` as heterozygotes,
+/// into the `0`/`1`/`2` genotype codes.
+///
+/// At every site the most common allele becomes `0` and the second most common `1`;
+/// calls carrying any other allele become `3`, so that multi-allelic sites stay visible
+/// to the site filters. Digits and other characters are kept.
+fn recode_diploid(records: &mut [(String, Vec<u8>)]) {
+    const ALLELES: &[u8; 4] = b"ACGT";
+    let index = |allele: u8| ALLELES.iter().position(|&a| a == allele).unwrap();
+    let lseq = records.first().map_or(0, |(_, seq)| seq.len());
+    for i in 0..lseq {
+        let mut counts = [0u32; 4];
+        for (_, seq) in records.iter() {
+            if let Some((a, b)) = iupac_alleles(seq[i]) {
+                counts[index(a)] += 1;
+                counts[index(b)] += 1;
+            }
+        }
+        let mut order = [0, 1, 2, 3];
+        order.sort_by_key(|&k| std::cmp::Reverse(counts[k]));
+        let (first, second) = (ALLELES[order[0]], ALLELES[order[1]]);
+        for (_, seq) in records.iter_mut() {
+            if let Some((a, b)) = iupac_alleles(seq[i]) {
+                seq[i] = if a == first && b == first {
+                    b'0'
+                } else if a == second && b == second {
+                    b'1'
+                } else if (a == first && b == second) || (a == second && b == first) {
+                    b'2'
+                } else {
+                    b'3'
+                };
             }
-            ofp.write(
-                format!(
-                    "{:>4}{:>5}{:>5}{:>5}{:>5}{:>5}\n",
-                    i + 1,
-                    row.column("N")?.u32()?.get(0).unwrap(),
-                    row.column("T")?.u32()?.get(0).unwrap(),
-                    row.column("C")?.u32()?.get(0).unwrap(),
-                    row.column("A")?.u32()?.get(0).unwrap(),
-                    row.column("G")?.u32()?.get(0).unwrap(),
-                )
-                .as_bytes(),
-            )?;
-            result.vstack_mut(&row)?;
         }
-        result.rechunk();
-        Ok(result)
     }
 }
 
+#[test]
+fn test_recode_diploid() {
+    let records = vec![
+        ("S1".to_string(), b"ACGT".to_vec()),
+        ("S2".to_string(), b"RCGT".to_vec()),
+        ("S3".to_string(), b"GCKT".to_vec()),
+        ("S4".to_string(), b"TCGT".to_vec()),
+    ];
+    let seqs = Seqs::from_records(records, Ploidy::Diploid).unwrap();
+    assert!(seqs["S2"].series_equal(&Series::new("S2", [4u8, 2, 2, 2])));
+    assert!(seqs["S3"].series_equal(&Series::new("S3", [3u8, 2, 4, 2])));
+    assert!(seqs["S4"].series_equal(&Series::new("S4", [5u8, 2, 2, 2])));
+    assert_eq!(seqs.missing_count(3).unwrap(), 0);
+    let nall = seqs.allele_count(None).unwrap();
+    assert_eq!(nall.column("G").unwrap().u32().unwrap().get(0), Some(2));
+    assert_eq!(nall.column("N").unwrap().u32().unwrap().get(0), Some(0));
+    assert_eq!(nall.column("T").unwrap().u32().unwrap().get(0), Some(3));
+    assert_eq!(nall.column("C").unwrap().u32().unwrap().get(0), Some(3));
+    assert_eq!(nall.column("C").unwrap().u32().unwrap().get(2), Some(1));
+}
+
 impl std::ops::Index<&str> for Seqs {
     type Output = polars::series::Series;
 
@@ -239,29 +602,241 @@ impl std::ops::Index<&str> for Seqs {
 
 /// Ploidy (/ˈplɔɪdi/) is the number of complete sets of chromosomes in a cell,
 /// and hence the number of possible alleles for autosomal and pseudoautosomal genes.
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize)]
 pub enum Ploidy {
     Haploid = 1,
     Diploid = 2,
 }
 
-impl From<char> for Ploidy {
-    fn from(value: char) -> Self {
+impl std::str::FromStr for Ploidy {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> std::result::Result<Ploidy, Self::Err> {
+        match s {
+            "1" => Ok(Ploidy::Haploid),
+            "2" => Ok(Ploidy::Diploid),
+            _ => Err(Self::Err::new("Variant not found")),
+        }
+    }
+}
+
+impl TryFrom<char> for Ploidy {
+    type Error = crate::Error;
+    fn try_from(value: char) -> std::result::Result<Self, Self::Error> {
         match value {
-            '1' => Ploidy::Haploid,
-            '2' => Ploidy::Diploid,
-            _ => Err(crate::Error::new("Variant not found")).unwrap(),
+            '1' => Ok(Ploidy::Haploid),
+            '2' => Ok(Ploidy::Diploid),
+            _ => Err(Self::Error::new("Variant not found")),
         }
     }
 }
 
 pub fn read_sites(path: &PathBuf) -> Result<Seqs> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
+    let mut reader = open(path)?;
     parse_sites(&mut reader)
 }
 
+/// Read sample names, one per line.
+pub fn read_sample_list(path: &Path) -> Result<Vec<String>> {
+    open(path)?
+        .lines()
+        .map(|line| -> Result<String> { Ok(line?.trim().to_string()) })
+        .filter(|name| !matches!(name, Ok(n) if n.is_empty()))
+        .collect()
+}
+
+/// Read the `(start, end]` intervals of a BED file in bp, only those on `chromosome` if given.
+/// Track, browser and comment lines are skipped. Without `chromosome`, the intervals must all
+/// lie on one chromosome.
+pub fn read_bed(path: &Path, chromosome: Option<&str>) -> Result<Vec<(f64, f64)>> {
+    parse_bed(open(path)?, chromosome)
+}
+
+fn parse_bed(reader: impl BufRead, chromosome: Option<&str>) -> Result<Vec<(f64, f64)>> {
+    let mut intervals = vec![];
+    let mut chromosomes: Vec<String> = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty()
+            || ["#", "track", "browser"]
+                .iter()
+                .any(|p| line.starts_with(p))
+        {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            return Err(anyhow::anyhow!("Malformed BED line: {}", line));
+        }
+        if chromosome.is_none() && !chromosomes.iter().any(|c| c == fields[0]) {
+            chromosomes.push(fields[0].to_string());
+        }
+        if chromosome.map_or(true, |c| fields[0] == c) {
+            intervals.push((fields[1].parse()?, fields[2].parse()?));
+        }
+    }
+    if chromosomes.len() > 1 {
+        return Err(anyhow::anyhow!(
+            "BED intervals on chromosomes {}, select one with --chromosome",
+            chromosomes.join(", ")
+        ));
+    }
+    Ok(intervals)
+}
+
+#[test]
+fn test_parse_bed() {
+    let content = "track name=test\nchr1\t10\t20\nchr2\t5\t8\tname\n";
+    assert_eq!(
+        parse_bed(content.as_bytes(), Some("chr2")).unwrap(),
+        vec![(5., 8.)]
+    );
+    assert!(parse_bed(content.as_bytes(), None).is_err());
+    assert_eq!(
+        parse_bed("chr1\t10\t20\n".as_bytes(), None).unwrap(),
+        vec![(10., 20.)]
+    );
+}
+
+/// Read `(sample, population)` pairs from a tab-separated sample sheet
+/// whose header names a `sample` and a `population` column.
+pub fn read_sample_sheet(path: &Path) -> Result<Vec<(String, String)>> {
+    parse_sample_sheet(open(path)?)
+}
+
+fn parse_sample_sheet(reader: impl BufRead) -> Result<Vec<(String, String)>> {
+    let mut lines = reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()));
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Empty sample sheet"))??;
+    let column = |name: &str| {
+        header
+            .split('\t')
+            .position(|c| c.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow::anyhow!("Sample sheet has no `{}` column", name))
+    };
+    let (sample, population) = (column("sample")?, column("population")?);
+    lines
+        .map(|line| {
+            let line = line?;
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            match (fields.get(sample), fields.get(population)) {
+                (Some(s), Some(p)) if !p.is_empty() => Ok((s.to_string(), p.to_string())),
+                _ => Err(anyhow::anyhow!("Malformed sample sheet line: {}", line)),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_sample_sheet() {
+    let content = "sample\tsex\tpopulation\nNA1\tF\tYRI\n\nNA2\tM\tCEU\n";
+    let sheet = parse_sample_sheet(content.as_bytes()).unwrap();
+    assert_eq!(
+        sheet,
+        vec![
+            ("NA1".to_string(), "YRI".to_string()),
+            ("NA2".to_string(), "CEU".to_string())
+        ]
+    );
+    assert!(parse_sample_sheet("sample\tpop\n".as_bytes()).is_err());
+}
+
+/// Layout of an input alignment.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum SeqFormat {
+    /// LDhat FASTA-style file, the `nseq lseq ploidy` header line may be omitted
+    Ldhat,
+    /// LDhat sites file with 0/1 or 0/1/2 genotype codes, such as `convert` output
+    Genotypes,
+    /// Genotype matrix written as Parquet
+    Parquet,
+    /// PHYLIP, sequential or interleaved
+    Phylip,
+    /// NEXUS `DATA` or `CHARACTERS` block
+    Nexus,
+    /// `ms`/`msprime` haplotypes, one alignment per replicate
+    Ms,
+    /// PLINK binary `.bed`/`.bim`/`.fam` fileset
+    Plink,
+}
+
+impl SeqFormat {
+    /// Guess the format from the file extension, ignoring `.gz` and `.zst`.
+    pub fn from_path(path: &Path) -> Self {
+        let mut path = path.to_path_buf();
+        if matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("gz" | "zst")
+        ) {
+            path.set_extension("");
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("parquet") => SeqFormat::Parquet,
+            Some("phy" | "phylip") => SeqFormat::Phylip,
+            Some("nex" | "nexus" | "nxs") => SeqFormat::Nexus,
+            Some("ms") => SeqFormat::Ms,
+            Some("bed") => SeqFormat::Plink,
+            _ => SeqFormat::Ldhat,
+        }
+    }
+}
+
+/// Read an alignment in the given format.
+/// `ploidy` is only used by formats that do not record it.
+pub fn read_seqs(path: &PathBuf, format: SeqFormat, ploidy: Ploidy) -> Result<Seqs> {
+    match format {
+        SeqFormat::Ldhat => {
+            let mut reader = open(path)?;
+            if reader.fill_buf()?.starts_with(b">") {
+                parse_fasta(reader, ploidy)
+            } else {
+                parse_sites(&mut reader)
+            }
+        }
+        SeqFormat::Genotypes => read_genotypes(path),
+        SeqFormat::Parquet => read_parquet_sites(path, ploidy),
+        SeqFormat::Phylip => read_phylip(path, ploidy),
+        SeqFormat::Nexus => read_nexus(path, ploidy),
+        SeqFormat::Plink => read_plink(path, None, None).map(|(seqs, _)| seqs),
+        SeqFormat::Ms => Err(anyhow::anyhow!(
+            "ms output holds one alignment per replicate, read it with `read_ms`"
+        )),
+    }
+}
+
+/// Load a genotype matrix written as Parquet, one `u8` column of base codes per sample.
+pub fn read_parquet_sites(path: &PathBuf, ploidy: Ploidy) -> Result<Seqs> {
+    let file = File::open(path)?;
+    let data = ParquetReader::new(file).finish()?;
+    let data = DataFrame::new(
+        data.iter()
+            .map(|s| s.cast(&DataType::UInt8))
+            .collect::<PolarsResult<Vec<_>>>()?,
+    )?;
+    Ok(Seqs { ploidy, data })
+}
+
+#[test]
+fn test_parquet_sites_roundtrip() {
+    let seqs = Seqs {
+        ploidy: Ploidy::Haploid,
+        data: df!(
+            "SampleA" => &[2u8, 3, 1],
+            "SampleB" => &[4u8, 5, 2],
+        )
+        .unwrap(),
+    };
+    let path = std::env::temp_dir().join("ldhat_test_parquet_sites.parquet");
+    seqs.write_parquet(&path).unwrap();
+    let read = read_parquet_sites(&path, Ploidy::Haploid).unwrap();
+    assert_eq!(read.names(), vec!["SampleA", "SampleB"]);
+    assert_eq!(read.data, seqs.data);
+}
+
 /// Backward compatible for original code
+#[derive(Debug, Clone, Copy)]
 pub enum Base {
     N = 1,
     T,
@@ -270,6 +845,19 @@ pub enum Base {
     G,
 }
 
+impl Base {
+    /// Decode a character of an alignment, where `0123` stand for `TCAG`.
+    pub fn from_ascii(value: u8) -> Self {
+        match value {
+            b'0' | b'T' | b't' => Base::T,
+            b'1' | b'C' | b'c' => Base::C,
+            b'2' | b'A' | b'a' => Base::A,
+            b'3' | b'G' | b'g' => Base::G,
+            _ => Base::N,
+        }
+    }
+}
+
 impl From<u8> for Base {
     fn from(value: u8) -> Self {
         match value {
@@ -300,14 +888,15 @@ impl PartialEq for Base {
     }
 }
 
-fn parse_sites(reader: &mut impl BufRead) -> Result<Seqs> {
+/// Parse the `nseq lseq ploidy` header line of LDhat sites files.
+fn parse_header(reader: &mut impl BufRead) -> Result<(usize, usize, Ploidy)> {
     use nom::character::complete::{digit1, line_ending, one_of, space1};
     use nom::combinator::map_res;
     use nom::sequence::terminated;
     let mut buf = String::new();
     reader.read_line(&mut buf)?;
     let first_line = buf.as_str();
-    let (_, (nseq, lseq, ploidy)) = nom::sequence::tuple((
+    let (_, header) = nom::sequence::tuple((
         terminated(
             map_res(digit1::<_, (_, nom::error::ErrorKind)>, str::parse::<usize>),
             space1,
@@ -316,32 +905,130 @@ fn parse_sites(reader: &mut impl BufRead) -> Result<Seqs> {
             map_res(digit1::<_, (_, nom::error::ErrorKind)>, str::parse::<usize>),
             space1,
         ),
-        terminated(
-            map_res(one_of("12"), |c| Ok::<_, crate::Error>(Ploidy::from(c))),
-            line_ending,
-        ),
+        terminated(map_res(one_of("12"), Ploidy::try_from), line_ending),
     ))(first_line)
-    .unwrap();
-    let reader = fasta::Reader::new(reader);
-    let data = DataFrame::from_iter(reader.records().into_iter().map(|res| {
-        let rec = res.expect("Error during fasta record parsing");
-        let ret = Series::new(
-            rec.id(),
-            rec.seq()
-                .iter()
-                .map(|&x| match x {
-                    48 | 84 | 116 => Base::T,
-                    49 | 67 | 99 => Base::C,
-                    50 | 65 | 97 => Base::A,
-                    51 | 71 | 103 => Base::G,
-                    _ => Base::N,
-                } as u8)
-                .collect::<Vec<u8>>(),
-        );
-        ret
-    }));
-    assert_eq!(data.shape(), (lseq, nseq));
-    Ok(Seqs { ploidy, data })
+    .map_err(|e| {
+        anyhow::anyhow!(
+            "Malformed `nseq lseq ploidy` header {:?}: {}",
+            first_line.trim_end(),
+            e
+        )
+    })?;
+    Ok(header)
+}
+
+fn check_header(seqs: &Seqs, nseq: usize, lseq: usize) -> Result<()> {
+    if seqs.shape() != (lseq, nseq) {
+        return Err(anyhow::anyhow!(
+            "Header declares {} sequences of length {} but found {} of length {}",
+            nseq,
+            lseq,
+            seqs.shape().1,
+            seqs.shape().0
+        ));
+    }
+    Ok(())
+}
+
+fn parse_sites(reader: &mut impl BufRead) -> Result<Seqs> {
+    let (nseq, lseq, ploidy) = parse_header(reader)?;
+    let seqs = parse_fasta(reader, ploidy)?;
+    check_header(&seqs, nseq, lseq)?;
+    Ok(seqs)
+}
+
+/// Read an LDhat sites file with genotype codes, such as the output of `convert`.
+pub fn read_genotypes(path: &PathBuf) -> Result<Seqs> {
+    let mut reader = open(path)?;
+    parse_genotypes(&mut reader)
+}
+
+/// Parse genotype codes: `0`/`1` for haploid data, where base letters are kept for
+/// multi-allelic sites, or `0`/`1`/`2` for diploid data; `?`, `N` and `-` are missing.
+/// Codes decode into the same slots as in [`parse_sites`], but anything else is an error
+/// rather than a base.
+fn parse_genotypes(reader: &mut impl BufRead) -> Result<Seqs> {
+    let (nseq, lseq, ploidy) = parse_header(reader)?;
+    let valid: &[u8] = match ploidy {
+        Ploidy::Haploid => b"01ACGTacgt?Nn-",
+        Ploidy::Diploid => b"012?Nn-",
+    };
+    let records = fasta::Reader::new(reader)
+        .records()
+        .map(|res| {
+            let rec = res?;
+            if let Some(&code) = rec.seq().iter().find(|c| !valid.contains(c)) {
+                return Err(anyhow::anyhow!(
+                    "Invalid {:?} genotype code '{}' in {}",
+                    ploidy,
+                    code as char,
+                    rec.id()
+                ));
+            }
+            Ok((rec.id().to_string(), rec.seq().to_vec()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let seqs = Seqs::from_records(records, ploidy)?;
+    check_header(&seqs, nseq, lseq)?;
+    Ok(seqs)
+}
+
+#[test]
+fn test_parse_genotypes() {
+    let content = "3 3 2\n>SampleA\n012\n>SampleB\n22?\n>SampleC\n001\n";
+    let mut reader = std::io::BufReader::new(content.as_bytes());
+    let seqs = parse_genotypes(&mut reader).unwrap();
+    assert_eq!(seqs.ploidy, Ploidy::Diploid);
+    assert_eq!(
+        seqs.allele_count(None).unwrap(),
+        df!(
+            "N" => &[0u32, 0, 2],
+            "T" => &[5u32, 3, 1],
+            "C" => &[1u32, 3, 3],
+            "A" => &[0u32, 0, 0],
+            "G" => &[0u32, 0, 0],
+        )
+        .unwrap()
+    );
+    let content = "1 3 2\n>SampleA\n013\n";
+    let mut reader = std::io::BufReader::new(content.as_bytes());
+    assert!(parse_genotypes(&mut reader).is_err());
+    let content = ">SampleA\n012\n";
+    let mut reader = std::io::BufReader::new(content.as_bytes());
+    assert!(parse_genotypes(&mut reader).is_err());
+}
+
+/// Parse FASTA records without the LDhat header line, e.g. an alignment from MAFFT or MUSCLE.
+fn parse_fasta(reader: impl BufRead, ploidy: Ploidy) -> Result<Seqs> {
+    let records = fasta::Reader::new(reader)
+        .records()
+        .map(|res| {
+            let rec = res?;
+            Ok((rec.id().to_string(), rec.seq().to_vec()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if records.is_empty() {
+        return Err(anyhow::anyhow!("No FASTA records found"));
+    }
+    Seqs::from_records(records, ploidy)
+}
+
+#[test]
+fn test_parse_fasta() {
+    let content = r#">SampleA
+TCCGC??RTT
+>SampleB
+TACGC??GTA
+"#;
+    let seqs = parse_fasta(content.as_bytes(), Ploidy::Diploid).unwrap();
+    assert_eq!(seqs.ploidy, Ploidy::Diploid);
+    assert_eq!(seqs.shape(), (10, 2));
+    let unequal = ">SampleA\nTCCGC\n>SampleB\nTACG\n";
+    let err = parse_fasta(unequal.as_bytes(), Ploidy::Haploid).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Sequence SampleB has 4 sites but SampleA has 5"
+    );
 }
 
 #[test]
@@ -0,0 +1,197 @@
+use super::{parse_header, recode_diploid, GenotypeMatrix, Ploidy, Seqs, GZIP_MAGIC, ZSTD_MAGIC};
+use crate::LDhatResult as Result;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Where one sequence of a sites file lies on disk.
+#[derive(Debug, Clone, PartialEq)]
+struct Record {
+    name: String,
+    /// Byte offset of the first site
+    offset: u64,
+    /// Sites per full line
+    line_sites: u64,
+    /// Bytes per full line, including the line ending
+    line_bytes: u64,
+}
+
+/// Byte offsets of the sequences of an uncompressed LDhat sites file, like a FASTA `.fai`,
+/// so that any range of sites can be read without loading the whole file.
+///
+/// Every line of a sequence but its last must hold the same number of sites.
+#[derive(Debug, Clone)]
+pub struct SitesIndex {
+    path: PathBuf,
+    pub ploidy: Ploidy,
+    /// Number of sites
+    pub lseq: usize,
+    records: Vec<Record>,
+    /// Indices of the records read, see [`Self::retain`]
+    selected: Vec<usize>,
+}
+
+impl SitesIndex {
+    pub fn build(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let magic = reader.fill_buf()?;
+        if magic.starts_with(&GZIP_MAGIC) || magic.starts_with(&ZSTD_MAGIC) {
+            return Err(anyhow::anyhow!(
+                "{} is compressed, chunked reading needs an uncompressed sites file",
+                path.display()
+            ));
+        }
+        if magic.starts_with(b">") {
+            return Err(anyhow::anyhow!(
+                "{} has no `nseq lseq ploidy` header, chunked reading needs an LDhat sites file",
+                path.display()
+            ));
+        }
+        let mut line = vec![];
+        let mut offset = reader.read_until(b'\n', &mut line)? as u64;
+        let (nseq, lseq, ploidy) = parse_header(&mut line.as_slice())?;
+        let mut records: Vec<Record> = vec![];
+        let mut lengths: Vec<u64> = vec![];
+        // Whether the current sequence had a line shorter than the first one
+        let mut short = false;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            if read == 0 {
+                break;
+            }
+            let content = line.strip_suffix(b"\n").unwrap_or(&line);
+            let content = content.strip_suffix(b"\r").unwrap_or(content);
+            if let Some(name) = content.strip_prefix(b">") {
+                records.push(Record {
+                    name: String::from_utf8_lossy(name).trim().to_string(),
+                    offset: offset + read,
+                    line_sites: 0,
+                    line_bytes: 0,
+                });
+                lengths.push(0);
+                short = false;
+            } else if let (Some(record), Some(length)) = (records.last_mut(), lengths.last_mut()) {
+                let sites = content.len() as u64;
+                if record.line_sites == 0 {
+                    record.line_sites = sites;
+                    record.line_bytes = read;
+                } else if (short && sites > 0) || sites > record.line_sites {
+                    return Err(anyhow::anyhow!(
+                        "Sequence {} has lines of unequal length and cannot be read in chunks",
+                        record.name
+                    ));
+                }
+                short |= sites < record.line_sites;
+                *length += sites;
+            } else if !content.is_empty() {
+                return Err(anyhow::anyhow!("Sites before the first `>` name line"));
+            }
+            offset += read;
+        }
+        if records.len() != nseq || lengths.iter().any(|&l| l != lseq as u64) {
+            return Err(anyhow::anyhow!(
+                "Header declares {} sequences of length {} but found {} of lengths {:?}",
+                nseq,
+                lseq,
+                records.len(),
+                lengths
+            ));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            ploidy,
+            lseq,
+            selected: (0..records.len()).collect(),
+            records,
+        })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.selected
+            .iter()
+            .map(|&k| self.records[k].name.as_str())
+            .collect()
+    }
+
+    /// Only read the sequences whose names satisfy `predicate`.
+    ///
+    /// Diploid calls are still recoded against every sequence of the file, as when it is
+    /// read whole, so the genotypes do not depend on which samples are kept.
+    pub fn retain(&mut self, predicate: impl Fn(&str) -> bool) {
+        let records = &self.records;
+        self.selected.retain(|&k| predicate(&records[k].name));
+    }
+
+    /// Sites `start..end` of every sequence.
+    pub fn read(&self, start: usize, end: usize) -> Result<Seqs> {
+        Seqs::from_records(self.read_records(start, end)?, self.ploidy)
+    }
+
+    /// Sites `start..end` of every sequence, straight into a bit-packed matrix.
+    pub fn read_matrix(&self, start: usize, end: usize) -> Result<GenotypeMatrix> {
+        GenotypeMatrix::from_records(self.read_records(start, end)?, self.ploidy)
+    }
+
+    fn read_records(&self, start: usize, end: usize) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut file = File::open(&self.path)?;
+        let mut records = self
+            .records
+            .iter()
+            .map(|record| {
+                let bytes = |site: usize| {
+                    let site = site as u64;
+                    record.offset
+                        + site / record.line_sites * record.line_bytes
+                        + site % record.line_sites
+                };
+                let from = bytes(start);
+                let mut buf = vec![0u8; (bytes(end - 1) + 1 - from) as usize];
+                file.seek(SeekFrom::Start(from))?;
+                file.read_exact(&mut buf)?;
+                buf.retain(|b| !b.is_ascii_whitespace());
+                Ok((record.name.clone(), buf))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if self.ploidy == Ploidy::Diploid {
+            recode_diploid(&mut records);
+        }
+        let mut records: Vec<_> = records.into_iter().map(Some).collect();
+        Ok(self
+            .selected
+            .iter()
+            .filter_map(|&k| records[k].take())
+            .collect())
+    }
+}
+
+#[test]
+fn test_sites_index() {
+    let path = std::env::temp_dir().join("ldhat_test_sites_index.txt");
+    std::fs::write(
+        &path,
+        "3 7 1\n>SampleA\nTCCG\nCAA\n>SampleB\nTACG\nCAA\n>SampleC\nTCCG\nCTT\n",
+    )
+    .unwrap();
+    let mut index = SitesIndex::build(&path).unwrap();
+    let fasta = std::env::temp_dir().join("ldhat_test_sites_index.fa");
+    std::fs::write(&fasta, ">SampleA\nTCCG\n>SampleB\nTACG\n").unwrap();
+    assert!(SitesIndex::build(&fasta).is_err());
+    assert_eq!(index.lseq, 7);
+    assert_eq!(index.names(), vec!["SampleA", "SampleB", "SampleC"]);
+    index.retain(|name| name != "SampleA");
+    let chunk = index.read(2, 6).unwrap();
+    assert_eq!(chunk.names(), vec!["SampleB", "SampleC"]);
+    assert!(chunk["SampleC"].series_equal(&polars::series::Series::new("SampleC", [3u8, 5, 3, 2])));
+    assert_eq!(
+        index.read_matrix(2, 6).unwrap().to_seqs().unwrap().data,
+        chunk.data
+    );
+
+    let path = std::env::temp_dir().join("ldhat_test_sites_index_diploid.txt");
+    std::fs::write(&path, "3 2 2\n>SampleA\nAC\n>SampleB\nAC\n>SampleC\nGG\n").unwrap();
+    let mut index = SitesIndex::build(&path).unwrap();
+    index.retain(|name| name == "SampleC");
+    let chunk = index.read(0, 2).unwrap();
+    assert!(chunk["SampleC"].series_equal(&polars::series::Series::new("SampleC", [3u8, 3])));
+}
@@ -0,0 +1,216 @@
+use super::{recode_diploid, Base, Genotypes, Ploidy, Seqs};
+use crate::LDhatResult as Result;
+use polars::prelude::*;
+use rayon::prelude::*;
+
+/// Site-major genotype store with 2 bits per call and a missing mask.
+///
+/// Each site holds one bit per sample in three bitplanes: the low and high bits of the
+/// call (`T`, `C`, `A`, `G` as `0..4`) and whether it is missing (`N`).
+/// Counting an allele at a site is then a popcount over a few words.
+/// Diploid data keep the genotype slots of [`Seqs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenotypeMatrix {
+    pub ploidy: Ploidy,
+    names: Vec<String>,
+    nsites: usize,
+    /// Number of `u64` words per site
+    words: usize,
+    lo: Vec<u64>,
+    hi: Vec<u64>,
+    missing: Vec<u64>,
+}
+
+impl GenotypeMatrix {
+    /// An all-missing matrix of `nsites` sites for the samples `names`.
+    pub fn new(names: Vec<String>, nsites: usize, ploidy: Ploidy) -> Self {
+        let words = (names.len() + 63) / 64;
+        Self {
+            ploidy,
+            names,
+            nsites,
+            words,
+            lo: vec![0; nsites * words],
+            hi: vec![0; nsites * words],
+            missing: vec![u64::MAX; nsites * words],
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+
+    /// `(sites, samples)`, like [`Seqs::shape`].
+    pub fn shape(&self) -> (usize, usize) {
+        (self.nsites, self.names.len())
+    }
+
+    fn offset(&self, site: usize, sample: usize) -> (usize, u64) {
+        (site * self.words + sample / 64, 1 << (sample % 64))
+    }
+
+    pub fn get(&self, site: usize, sample: usize) -> Base {
+        let (w, bit) = self.offset(site, sample);
+        if self.missing[w] & bit != 0 {
+            return Base::N;
+        }
+        let lo = (self.lo[w] & bit != 0) as u8;
+        let hi = (self.hi[w] & bit != 0) as u8;
+        Base::from(Base::T as u8 + lo + 2 * hi)
+    }
+
+    pub fn set(&mut self, site: usize, sample: usize, base: Base) {
+        let (w, bit) = self.offset(site, sample);
+        let code = match base {
+            Base::N => {
+                self.missing[w] |= bit;
+                return;
+            }
+            base => base as u8 - Base::T as u8,
+        };
+        self.missing[w] &= !bit;
+        for (plane, set) in [(&mut self.lo, code & 1 != 0), (&mut self.hi, code & 2 != 0)] {
+            if set {
+                plane[w] |= bit;
+            } else {
+                plane[w] &= !bit;
+            }
+        }
+    }
+
+    /// Calls of every sample at `site`.
+    pub fn row(&self, site: usize) -> impl Iterator<Item = Base> + '_ {
+        (0..self.names.len()).map(move |j| self.get(site, j))
+    }
+
+    /// Calls of `sample` at every site.
+    pub fn column(&self, sample: usize) -> impl Iterator<Item = Base> + '_ {
+        (0..self.nsites).map(move |i| self.get(i, sample))
+    }
+
+    /// Number of samples at `site` with each call, in `N`, `T`, `C`, `A`, `G` order.
+    pub fn call_count(&self, site: usize) -> [u32; 5] {
+        let mut counts = [0u32; 5];
+        let range = site * self.words..(site + 1) * self.words;
+        for (k, w) in range.enumerate() {
+            // Padding bits past the last sample are never counted
+            let valid = match self.names.len() - k * 64 {
+                n if n >= 64 => u64::MAX,
+                n => (1u64 << n) - 1,
+            };
+            let (lo, hi, missing) = (self.lo[w], self.hi[w], self.missing[w] & valid);
+            let present = !missing & valid;
+            counts[0] += missing.count_ones();
+            counts[1] += (present & !lo & !hi).count_ones();
+            counts[2] += (present & lo & !hi).count_ones();
+            counts[3] += (present & !lo & hi).count_ones();
+            counts[4] += (present & lo & hi).count_ones();
+        }
+        counts
+    }
+
+    /// Build the matrix from named sequences of characters, like [`Seqs::from_records`].
+    pub fn from_records(mut records: Vec<(String, Vec<u8>)>, ploidy: Ploidy) -> Result<Self> {
+        let lseq = records.first().map_or(0, |(_, seq)| seq.len());
+        if let Some((name, seq)) = records.iter().find(|(_, seq)| seq.len() != lseq) {
+            return Err(anyhow::anyhow!(
+                "Sequence {} has {} sites but {} has {}",
+                name,
+                seq.len(),
+                records[0].0,
+                lseq
+            ));
+        }
+        if ploidy == Ploidy::Diploid {
+            recode_diploid(&mut records);
+        }
+        let names = records.iter().map(|(name, _)| name.clone()).collect();
+        let mut matrix = Self::new(names, lseq, ploidy);
+        for (j, (_, seq)) in records.iter().enumerate() {
+            for (i, &c) in seq.iter().enumerate() {
+                matrix.set(i, j, Base::from_ascii(c));
+            }
+        }
+        Ok(matrix)
+    }
+
+    pub fn from_seqs(seqs: &Seqs) -> Result<Self> {
+        let names = seqs.names().into_iter().map(String::from).collect();
+        let mut matrix = Self::new(names, seqs.len(), seqs.ploidy);
+        for (j, column) in seqs.data.iter().enumerate() {
+            for (i, value) in column.u8()?.into_iter().enumerate() {
+                matrix.set(i, j, Base::from(value.unwrap_or(Base::N as u8)));
+            }
+        }
+        Ok(matrix)
+    }
+
+    pub fn to_seqs(&self) -> Result<Seqs> {
+        let columns = self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(j, name)| {
+                let values: Vec<u8> = self.column(j).map(|b| b as u8).collect();
+                Series::new(name, values)
+            })
+            .collect();
+        Ok(Seqs {
+            ploidy: self.ploidy,
+            data: DataFrame::new(columns)?,
+        })
+    }
+}
+
+impl Genotypes for GenotypeMatrix {
+    fn ploidy(&self) -> Ploidy {
+        self.ploidy
+    }
+
+    fn call_counts(&self) -> Result<Vec<[u32; 5]>> {
+        Ok((0..self.nsites)
+            .into_par_iter()
+            .map(|i| self.call_count(i))
+            .collect())
+    }
+
+    fn missing_count(&self, sample: usize) -> Result<u32> {
+        Ok(self.column(sample).filter(|&b| b == Base::N).count() as u32)
+    }
+}
+
+#[test]
+fn test_genotype_matrix() {
+    let names: Vec<String> = (0..70).map(|j| format!("S{}", j)).collect();
+    let data = DataFrame::new(
+        names
+            .iter()
+            .enumerate()
+            .map(|(j, name)| Series::new(name, [(j % 5) as u8 + 1, 2, 5]))
+            .collect(),
+    )
+    .unwrap();
+    let seqs = Seqs {
+        ploidy: Ploidy::Haploid,
+        data,
+    };
+    let matrix = GenotypeMatrix::from_seqs(&seqs).unwrap();
+    assert_eq!(matrix.shape(), (3, 70));
+    assert_eq!(matrix.call_count(0), [14, 14, 14, 14, 14]);
+    assert_eq!(matrix.call_count(1), [0, 70, 0, 0, 0]);
+    assert_eq!(matrix.row(2).filter(|b| *b == Base::G).count(), 70);
+    assert_eq!(matrix.call_counts().unwrap(), seqs.call_counts().unwrap());
+    assert_eq!(matrix.to_seqs().unwrap().data, seqs.data);
+    assert_eq!(matrix.missing_count(0).unwrap(), 1);
+    assert_eq!(seqs.missing_count(0).unwrap(), 1);
+
+    let records = vec![
+        ("S1".to_string(), b"AAC".to_vec()),
+        ("S2".to_string(), b"RCC".to_vec()),
+    ];
+    let matrix = GenotypeMatrix::from_records(records.clone(), Ploidy::Diploid).unwrap();
+    assert_eq!(
+        matrix.to_seqs().unwrap().data,
+        Seqs::from_records(records, Ploidy::Diploid).unwrap().data
+    );
+}
@@ -0,0 +1,125 @@
+use super::{open, Locs, Model, Ploidy, Seqs};
+use crate::LDhatResult as Result;
+use std::io::{BufRead, Lines};
+use std::iter::Peekable;
+use std::path::PathBuf;
+
+/// Iterator over the replicates of `ms`/`msprime`-style output.
+///
+/// Each replicate becomes a haploid [`Seqs`], with samples named by their row number,
+/// and a [`Locs`] whose relative positions are scaled by the sequence length.
+pub struct MsReader<R: BufRead> {
+    lines: Peekable<Lines<R>>,
+    length: f64,
+}
+
+pub fn read_ms(path: &PathBuf, length: f64) -> Result<MsReader<Box<dyn BufRead>>> {
+    Ok(MsReader::new(open(path)?, length))
+}
+
+impl<R: BufRead> MsReader<R> {
+    pub fn new(reader: R, length: f64) -> Self {
+        Self {
+            lines: reader.lines().peekable(),
+            length,
+        }
+    }
+
+    fn next_line(&mut self) -> Result<String> {
+        match self.lines.next() {
+            Some(line) => Ok(line?),
+            None => Err(anyhow::anyhow!("Truncated ms replicate")),
+        }
+    }
+
+    fn parse_replicate(&mut self) -> Result<(Seqs, Locs)> {
+        let segsites: usize = loop {
+            let line = self.next_line()?;
+            if let Some(n) = line.strip_prefix("segsites:") {
+                break n.trim().parse()?;
+            }
+        };
+        let data = if segsites == 0 {
+            vec![]
+        } else {
+            let line = self.next_line()?;
+            line.strip_prefix("positions:")
+                .ok_or_else(|| anyhow::anyhow!("Expected `positions:` after `segsites:`"))?
+                .split_whitespace()
+                .map(|p| Ok(p.parse::<f64>()? * self.length))
+                .collect::<Result<Vec<_>>>()?
+        };
+        if data.len() != segsites {
+            return Err(anyhow::anyhow!(
+                "{} positions for {} segregating sites",
+                data.len(),
+                segsites
+            ));
+        }
+        let mut records = vec![];
+        while let Some(Ok(line)) = self.lines.peek() {
+            let line = line.trim();
+            if segsites == 0 || line.is_empty() || line.starts_with("//") {
+                break;
+            }
+            records.push(((records.len() + 1).to_string(), line.as_bytes().to_vec()));
+            self.lines.next();
+        }
+        let seqs = Seqs::from_records(records, Ploidy::Haploid)?;
+        if seqs.len() != segsites && !seqs.names().is_empty() {
+            return Err(anyhow::anyhow!(
+                "Haplotypes have {} sites but segsites is {}",
+                seqs.len(),
+                segsites
+            ));
+        }
+        let locs = Locs {
+            data,
+            length: self.length,
+            model: Model::CrossingOver,
+            contig: None,
+        };
+        Ok((seqs, locs))
+    }
+}
+
+impl<R: BufRead> Iterator for MsReader<R> {
+    type Item = Result<(Seqs, Locs)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.lines.next()? {
+                Ok(line) if line.starts_with("//") => break,
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        Some(self.parse_replicate())
+    }
+}
+
+#[test]
+fn test_read_ms() {
+    let content = r#"ms 3 2 -t 2.0
+27473 36154 10290
+
+//
+segsites: 2
+positions: 0.1000 0.7500
+01
+10
+11
+
+//
+segsites: 0
+"#;
+    let replicates: Vec<_> = MsReader::new(content.as_bytes(), 2000.)
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(replicates.len(), 2);
+    let (seqs, locs) = &replicates[0];
+    assert_eq!(seqs.shape(), (2, 3));
+    assert_eq!(locs.data, vec![200., 1500.]);
+    assert!(seqs["1"].series_equal(&polars::series::Series::new("1", [2u8, 3])));
+    assert_eq!(replicates[1].0.shape(), (0, 0));
+}
@@ -0,0 +1,172 @@
+use super::{open, Ploidy, Seqs};
+use crate::LDhatResult as Result;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Read the matrix of the first NEXUS `DATA` or `CHARACTERS` block.
+pub fn read_nexus(path: &PathBuf, ploidy: Ploidy) -> Result<Seqs> {
+    let mut content = String::new();
+    open(path)?.read_to_string(&mut content)?;
+    parse_nexus(&content, ploidy)
+}
+
+/// Remove `[...]` comments.
+fn strip_comments(content: &str) -> String {
+    let mut depth = 0usize;
+    content
+        .chars()
+        .filter(|&c| match c {
+            '[' => {
+                depth += 1;
+                false
+            }
+            ']' if depth > 0 => {
+                depth -= 1;
+                false
+            }
+            _ => depth == 0,
+        })
+        .collect()
+}
+
+/// Value of `key=value` in a command such as `DIMENSIONS NTAX=3 NCHAR=12`,
+/// with or without spaces around the `=`.
+fn option<'a>(command: &'a str, key: &str) -> Option<&'a str> {
+    let lower = command.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(key) {
+        from += found + key.len();
+        if let Some(value) = command[from..].trim_start().strip_prefix('=') {
+            return value.split_whitespace().next();
+        }
+    }
+    None
+}
+
+/// Split a matrix row into its (possibly quoted) taxon name and residues.
+fn split_row(line: &str) -> (String, &str) {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix('\'') {
+        let end = rest.find('\'').unwrap_or(rest.len());
+        (rest[..end].to_string(), rest.get(end + 1..).unwrap_or(""))
+    } else {
+        let end = line.find(char::is_whitespace).unwrap_or(line.len());
+        (line[..end].to_string(), &line[end..])
+    }
+}
+
+fn parse_nexus(content: &str, ploidy: Ploidy) -> Result<Seqs> {
+    let content = strip_comments(content);
+    let lower = content.to_ascii_lowercase();
+    let block = ["begin data;", "begin characters;"]
+        .iter()
+        .filter_map(|b| lower.find(b))
+        .min()
+        .ok_or_else(|| anyhow::anyhow!("No DATA or CHARACTERS block in NEXUS file"))?;
+    let mut nchar = None;
+    let mut matchchar = None;
+    let mut interleave = false;
+    let mut matrix = None;
+    let mut offset = block;
+    for command in content[block..].split(';') {
+        let keyword = command.trim_start().to_ascii_lowercase();
+        if keyword.starts_with("dimensions") {
+            nchar = option(command, "nchar")
+                .map(str::parse::<usize>)
+                .transpose()?;
+        } else if keyword.starts_with("format") {
+            matchchar = option(command, "matchchar").and_then(|c| c.bytes().next());
+            // `INTERLEAVE` alone or with a YES or NO value
+            interleave = keyword.contains("interleave")
+                && !option(command, "interleave").map_or(false, |v| v.eq_ignore_ascii_case("no"));
+        } else if keyword.starts_with("matrix") {
+            let start = offset + lower[offset..].find("matrix").unwrap() + "matrix".len();
+            matrix = Some(&content[start..offset + command.len()]);
+            break;
+        } else if keyword.starts_with("end") {
+            break;
+        }
+        offset += command.len() + 1;
+    }
+    let matrix = matrix.ok_or_else(|| anyhow::anyhow!("No MATRIX in NEXUS data block"))?;
+    let mut records: Vec<(String, Vec<u8>)> = vec![];
+    for line in matrix.lines().filter(|line| !line.trim().is_empty()) {
+        // Rows of a sequential matrix may wrap, the taxon going on over unnamed lines
+        if let (false, Some(nchar), Some((_, seq))) = (interleave, nchar, records.last_mut()) {
+            if seq.len() < nchar {
+                seq.extend(line.bytes().filter(|b| !b.is_ascii_whitespace()));
+                continue;
+            }
+        }
+        let (name, rest) = split_row(line);
+        let seq = rest.bytes().filter(|b| !b.is_ascii_whitespace());
+        match records.iter_mut().find(|(n, _)| *n == name) {
+            // Interleaved matrices repeat the taxon names in every block
+            Some((_, existing)) => existing.extend(seq),
+            None => records.push((name, seq.collect())),
+        }
+    }
+    if let (Some(m), Some((_, first))) = (matchchar, records.first().cloned()) {
+        for (_, seq) in records.iter_mut().skip(1) {
+            for (x, &f) in seq.iter_mut().zip(first.iter()) {
+                if *x == m {
+                    *x = f;
+                }
+            }
+        }
+    }
+    if let Some(nchar) = nchar {
+        if let Some((name, seq)) = records.iter().find(|(_, seq)| seq.len() != nchar) {
+            return Err(anyhow::anyhow!(
+                "Taxon {} has {} characters but NCHAR={}",
+                name,
+                seq.len(),
+                nchar
+            ));
+        }
+    }
+    Seqs::from_records(records, ploidy)
+}
+
+#[test]
+fn test_parse_nexus() {
+    let content = r#"#NEXUS
+[ exported alignment ]
+BEGIN DATA;
+    DIMENSIONS NTAX=3 NCHAR=12;
+    FORMAT DATATYPE=DNA MISSING=? GAP=- MATCHCHAR=. INTERLEAVE;
+    MATRIX
+    SampleA    TCCGCAAG
+    SampleB    .A......
+    'Sample C' ....CTTG
+
+    SampleA    TTAC
+    SampleB    ...A
+    'Sample C' ....
+    ;
+END;
+"#;
+    let seqs = parse_nexus(content, Ploidy::Haploid).unwrap();
+    assert_eq!(seqs.names(), vec!["SampleA", "SampleB", "Sample C"]);
+    assert!(seqs["SampleB"].series_equal(&polars::series::Series::new(
+        "SampleB",
+        [2u8, 4, 3, 5, 3, 4, 4, 5, 2, 2, 4, 4]
+    )));
+    let sequential = r#"#NEXUS
+BEGIN DATA;
+    DIMENSIONS NTAX = 2 NCHAR = 12;
+    FORMAT DATATYPE = DNA;
+    MATRIX
+    SampleA    TCCGCAAG
+               TTAC
+    SampleB    TACGCAAGTTAA
+    ;
+END;
+"#;
+    let seqs = parse_nexus(sequential, Ploidy::Haploid).unwrap();
+    assert_eq!(seqs.names(), vec!["SampleA", "SampleB"]);
+    assert!(seqs["SampleA"].series_equal(&polars::series::Series::new(
+        "SampleA",
+        [2u8, 3, 3, 5, 3, 4, 4, 5, 2, 2, 4, 3]
+    )));
+}
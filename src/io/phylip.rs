@@ -0,0 +1,160 @@
+use super::{open, Ploidy, Seqs};
+use crate::LDhatResult as Result;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Read a relaxed or strict PHYLIP alignment, sequential or interleaved.
+pub fn read_phylip(path: &PathBuf, ploidy: Ploidy) -> Result<Seqs> {
+    let mut content = String::new();
+    open(path)?.read_to_string(&mut content)?;
+    parse_phylip(&content, ploidy)
+}
+
+/// Records of the sequences, by name.
+type Records = Vec<(String, Vec<u8>)>;
+
+/// Split the first line of a sequence into its name and residues.
+type SplitName = fn(&str) -> (String, Vec<u8>);
+
+/// Parse the lines after the header as `ntax` sequences of `nchar` sites, if they fit.
+type Layout = fn(&[&str], usize, usize, SplitName) -> Option<Records>;
+
+/// Split a line into its leading name and the residues after it, dropping whitespace.
+fn split_name(line: &str) -> (String, Vec<u8>) {
+    let line = line.trim();
+    let (name, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+    (name.to_string(), residues(rest))
+}
+
+/// Split a line of strict PHYLIP, whose name is padded to 10 characters and may
+/// run straight into the residues.
+fn split_strict(line: &str) -> (String, Vec<u8>) {
+    let end = line.char_indices().nth(10).map_or(line.len(), |(i, _)| i);
+    (line[..end].trim().to_string(), residues(&line[end..]))
+}
+
+fn residues(line: &str) -> Vec<u8> {
+    line.bytes().filter(|b| !b.is_ascii_whitespace()).collect()
+}
+
+fn parse_sequential(
+    lines: &[&str],
+    ntax: usize,
+    nchar: usize,
+    split: SplitName,
+) -> Option<Records> {
+    let mut records = Vec::with_capacity(ntax);
+    let mut lines = lines.iter();
+    for _ in 0..ntax {
+        let (name, mut seq) = split(lines.next()?);
+        while seq.len() < nchar {
+            seq.extend(residues(lines.next()?));
+        }
+        if seq.len() != nchar {
+            return None;
+        }
+        records.push((name, seq));
+    }
+    lines.next().is_none().then_some(records)
+}
+
+fn parse_interleaved(
+    lines: &[&str],
+    ntax: usize,
+    nchar: usize,
+    split: SplitName,
+) -> Option<Records> {
+    if lines.len() < ntax || lines.len() % ntax != 0 {
+        return None;
+    }
+    let mut records: Records = lines[..ntax].iter().map(|line| split(line)).collect();
+    for (i, line) in lines[ntax..].iter().enumerate() {
+        records[i % ntax].1.extend(residues(line));
+    }
+    records
+        .iter()
+        .all(|(_, seq)| seq.len() == nchar)
+        .then_some(records)
+}
+
+/// Parse PHYLIP, with names separated from the sequence by whitespace (relaxed) or
+/// padded to 10 characters (strict).
+/// The layout is taken from an `I` or `S` flag after the dimensions when present,
+/// otherwise interleaved is tried first and sequential second. Relaxed names are tried
+/// before strict ones.
+fn parse_phylip(content: &str, ploidy: Ploidy) -> Result<Seqs> {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Empty PHYLIP file"))?;
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    if fields.len() < 2 {
+        return Err(anyhow::anyhow!("PHYLIP header must be `ntax nchar`"));
+    }
+    let ntax: usize = fields[0].parse()?;
+    let nchar: usize = fields[1].parse()?;
+    let lines: Vec<&str> = lines.collect();
+    let (interleaved, sequential): (Layout, Layout) = (parse_interleaved, parse_sequential);
+    let layouts = match fields.get(2).map(|f| f.to_ascii_uppercase()).as_deref() {
+        Some("I") => vec![interleaved],
+        Some("S") => vec![sequential],
+        _ => vec![interleaved, sequential],
+    };
+    let records = [split_name as SplitName, split_strict]
+        .into_iter()
+        .find_map(|split| {
+            layouts
+                .iter()
+                .find_map(|parse| parse(&lines, ntax, nchar, split))
+        })
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "PHYLIP records do not match {} sequences of {} sites",
+                ntax,
+                nchar
+            )
+        })?;
+    Seqs::from_records(records, ploidy)
+}
+
+#[test]
+fn test_parse_phylip() {
+    let sequential = r#"3 12
+SampleA TCCGCAAG
+TTAC
+SampleB TACGCAAG TTAA
+SampleC TCCGCTTG
+TTAC
+"#;
+    let interleaved = r#"3 12
+SampleA TCCGCAAG
+SampleB TACGCAAG
+SampleC TCCGCTTG
+
+TTAC
+TTAA
+TTAC
+"#;
+    let strict = r#"3 12
+SampleA   TCCGCAAGTTAC
+SampleB   TACGCAAGTTAA
+Sample C  TCCGCTTGTTAC
+"#;
+    for content in [sequential, interleaved] {
+        let seqs = parse_phylip(content, Ploidy::Haploid).unwrap();
+        assert_eq!(seqs.names(), vec!["SampleA", "SampleB", "SampleC"]);
+        assert_eq!(seqs.shape(), (12, 3));
+        assert!(seqs["SampleB"].series_equal(&polars::series::Series::new(
+            "SampleB",
+            [2u8, 4, 3, 5, 3, 4, 4, 5, 2, 2, 4, 4]
+        )));
+    }
+    let seqs = parse_phylip(strict, Ploidy::Haploid).unwrap();
+    assert_eq!(seqs.names(), vec!["SampleA", "SampleB", "Sample C"]);
+    let strict = "2 4\nSample0001TCCG\nSample0002TACG\n";
+    let seqs = parse_phylip(strict, Ploidy::Haploid).unwrap();
+    assert_eq!(seqs.names(), vec!["Sample0001", "Sample0002"]);
+    assert!(
+        seqs["Sample0002"].series_equal(&polars::series::Series::new("Sample0002", [2u8, 4, 3, 5]))
+    );
+}
@@ -0,0 +1,214 @@
+use super::{open, Base, Locs, Model, Ploidy, Seqs};
+use crate::LDhatResult as Result;
+use polars::prelude::{DataFrame, NamedFrom, Series};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const BED_MAGIC: [u8; 3] = [0x6c, 0x1b, 0x01];
+
+/// A variant line of a `.bim` file.
+struct Variant {
+    chromosome: String,
+    position: f64,
+}
+
+/// The `.bed`/`.bim`/`.fam` paths sharing the prefix of `path`.
+fn fileset(path: &Path) -> (PathBuf, PathBuf, PathBuf) {
+    let prefix = match path.extension().and_then(|e| e.to_str()) {
+        Some("bed" | "bim" | "fam") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    };
+    let with = |ext: &str| PathBuf::from(format!("{}.{}", prefix.display(), ext));
+    (with("bed"), with("bim"), with("fam"))
+}
+
+/// Paths of the `.bed`, `.bim` and `.fam` files read by [`read_plink`].
+pub fn plink_files(path: &Path) -> [PathBuf; 3] {
+    let (bed, bim, fam) = fileset(path);
+    [bed, bim, fam]
+}
+
+/// Chromosomes of the `.bim` file of the fileset at `path`, in order of first appearance.
+pub fn plink_chromosomes(path: &Path) -> Result<Vec<String>> {
+    let (_, bim, _) = fileset(path);
+    let mut chromosomes: Vec<String> = vec![];
+    for variant in read_bim(&bim)? {
+        if !chromosomes.contains(&variant.chromosome) {
+            chromosomes.push(variant.chromosome);
+        }
+    }
+    Ok(chromosomes)
+}
+
+fn read_bim(path: &Path) -> Result<Vec<Variant>> {
+    open(path)?
+        .lines()
+        .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+        .map(|line| {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return Err(anyhow::anyhow!("Malformed .bim line: {}", line));
+            }
+            Ok(Variant {
+                chromosome: fields[0].to_string(),
+                position: fields[3].parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Individual IDs (second column) of a `.fam` file.
+fn read_fam(path: &Path) -> Result<Vec<String>> {
+    open(path)?
+        .lines()
+        .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+        .map(|line| {
+            let line = line?;
+            line.split_whitespace()
+                .nth(1)
+                .map(String::from)
+                .ok_or_else(|| anyhow::anyhow!("Malformed .fam line: {}", line))
+        })
+        .collect()
+}
+
+/// Decode a PLINK 2-bit genotype into the LDhat diploid coding that `parse_sites`
+/// gives the digits `0`, `1` and `2`.
+fn decode(bits: u8) -> Base {
+    match bits {
+        0b00 => Base::T, // homozygous for the first allele, `0`
+        0b11 => Base::C, // homozygous for the second allele, `1`
+        0b10 => Base::A, // heterozygous, `2`
+        _ => Base::N,    // 0b01 is missing
+    }
+}
+
+/// Read PLINK binary genotypes into a diploid [`Seqs`] with one column per individual,
+/// and a [`Locs`] of the `.bim` base-pair positions.
+///
+/// `path` is the `.bed` file or the common prefix of the fileset; the `.bim` and `.fam`
+/// files may be gzip or zstd compressed, like other text inputs.
+/// Variants can be restricted to one `chromosome`, and individuals to the IDs in `samples`.
+pub fn read_plink(
+    path: &Path,
+    chromosome: Option<&str>,
+    samples: Option<&[String]>,
+) -> Result<(Seqs, Locs)> {
+    let (bed, bim, fam) = fileset(path);
+    let variants = read_bim(&bim)?;
+    let individuals = read_fam(&fam)?;
+    let columns: Vec<usize> = match samples {
+        Some(samples) => {
+            if let Some(missing) = samples.iter().find(|s| !individuals.contains(s)) {
+                return Err(anyhow::anyhow!(
+                    "Sample {} not found in {}",
+                    missing,
+                    fam.display()
+                ));
+            }
+            (0..individuals.len())
+                .filter(|&j| samples.contains(&individuals[j]))
+                .collect()
+        }
+        None => (0..individuals.len()).collect(),
+    };
+    let rows: Vec<usize> = (0..variants.len())
+        .filter(|&i| chromosome.map_or(true, |c| variants[i].chromosome == c))
+        .collect();
+    if rows.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No variants selected from {}",
+            bim.display()
+        ));
+    }
+    let mut file = BufReader::new(File::open(&bed)?);
+    let mut magic = [0u8; 3];
+    file.read_exact(&mut magic)?;
+    if magic != BED_MAGIC {
+        return Err(anyhow::anyhow!(
+            "{} is not a SNP-major PLINK .bed file",
+            bed.display()
+        ));
+    }
+    let block = (individuals.len() + 3) / 4;
+    let mut values = vec![Vec::with_capacity(rows.len()); columns.len()];
+    let mut buf = vec![0u8; block];
+    for &i in &rows {
+        file.seek(SeekFrom::Start((BED_MAGIC.len() + i * block) as u64))?;
+        file.read_exact(&mut buf)?;
+        for (column, &j) in values.iter_mut().zip(&columns) {
+            let bits = (buf[j / 4] >> (2 * (j % 4))) & 0b11;
+            column.push(decode(bits) as u8);
+        }
+    }
+    let data = DataFrame::new(
+        columns
+            .iter()
+            .zip(values)
+            .map(|(&j, column)| Series::new(&individuals[j], column))
+            .collect(),
+    )?;
+    let positions: Vec<f64> = rows.iter().map(|&i| variants[i].position).collect();
+    if positions.windows(2).any(|w| w[1] < w[0]) {
+        return Err(anyhow::anyhow!(
+            "Variant positions are not increasing, select one chromosome"
+        ));
+    }
+    let first = &variants[rows[0]].chromosome;
+    let locs = Locs {
+        length: *positions.last().unwrap(),
+        data: positions,
+        model: Model::CrossingOver,
+        contig: rows
+            .iter()
+            .all(|&i| &variants[i].chromosome == first)
+            .then(|| first.clone()),
+    };
+    Ok((
+        Seqs {
+            ploidy: Ploidy::Diploid,
+            data,
+        },
+        locs,
+    ))
+}
+
+#[test]
+fn test_read_plink() {
+    let dir = std::env::temp_dir();
+    let prefix = dir.join("ldhat_test_plink");
+    std::fs::write(
+        dir.join("ldhat_test_plink.fam"),
+        "f1 s1 0 0 1 -9\nf2 s2 0 0 2 -9\nf3 s3 0 0 1 -9\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("ldhat_test_plink.bim"),
+        "1 rs1 0 100 A G\n1 rs2 0 250 C T\n2 rs3 0 50 A C\n",
+    )
+    .unwrap();
+    // s1 s2 s3 per variant: 00 10 11, 01 00 00, 11 11 11
+    std::fs::write(
+        dir.join("ldhat_test_plink.bed"),
+        [
+            0x6c,
+            0x1b,
+            0x01,
+            0b00_11_10_00,
+            0b00_00_00_01,
+            0b00_11_11_11,
+        ],
+    )
+    .unwrap();
+    let keep = vec!["s1".to_string(), "s2".to_string()];
+    let (seqs, locs) = read_plink(&prefix, Some("1"), Some(&keep)).unwrap();
+    assert_eq!(seqs.ploidy, Ploidy::Diploid);
+    assert_eq!(seqs.names(), vec!["s1", "s2"]);
+    assert!(seqs["s1"].series_equal(&Series::new("s1", [2u8, 1])));
+    assert!(seqs["s2"].series_equal(&Series::new("s2", [4u8, 2])));
+    assert_eq!(locs.data, vec![100., 250.]);
+    assert_eq!(locs.contig.as_deref(), Some("1"));
+    assert_eq!(plink_chromosomes(&prefix).unwrap(), vec!["1", "2"]);
+}
@@ -1,6 +1,11 @@
 pub mod commands;
+pub mod convert;
 pub mod error;
 pub mod io;
+pub mod map;
+pub mod output;
+#[cfg(feature = "python")]
+mod python;
 pub use error::Error;
 pub use io::read_locs;
 pub const SEQ_MAX: usize = 1000;
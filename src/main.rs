@@ -1,5 +1,5 @@
 use clap::Parser;
-use ldhat::commands::{Convert, Executable};
+use ldhat::commands::{Batch, Convert, Executable, Export, GlobalOptions, Stitch};
 use ldhat::LDhatResult as Result;
 
 #[derive(Parser)]
@@ -7,33 +7,42 @@ struct LDhatCLIOptions {
     #[command(subcommand)]
     action: LDhatAction,
     #[command(flatten)]
+    global: GlobalOptions,
+    #[command(flatten)]
     verbosity: clap_verbosity_flag::Verbosity,
 }
 
 #[derive(Parser)]
 enum LDhatAction {
     Convert(Convert),
+    Batch(Batch),
+    Stitch(Stitch),
+    Export(Export),
 }
 
 impl Executable for LDhatAction {
-    fn execute(&self) -> Result<()> {
+    fn execute(&self, global: &GlobalOptions) -> Result<()> {
         match self {
-            Self::Convert(options) => options.execute(),
+            Self::Convert(options) => options.execute(global),
+            Self::Batch(options) => options.execute(global),
+            Self::Stitch(options) => options.execute(global),
+            Self::Export(options) => options.execute(global),
         }
     }
 }
 
-impl Executable for LDhatCLIOptions {
-    fn execute(&self) -> Result<()> {
-        env_logger::Builder::new()
-            .filter_level(self.verbosity.log_level_filter())
-            .init();
-        self.action.execute()
-    }
-}
-
 fn main() -> Result<()> {
     let args = LDhatCLIOptions::parse();
-    args.execute()?;
+    env_logger::Builder::new()
+        .filter_level(args.verbosity.log_level_filter())
+        .init();
+    if args.global.threads > 0 {
+        // Polars sizes its own pool from this variable when it is first used
+        std::env::set_var("POLARS_MAX_THREADS", args.global.threads.to_string());
+    }
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.global.threads)
+        .build_global()?;
+    args.action.execute(&args.global)?;
     Ok(())
 }
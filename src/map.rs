@@ -0,0 +1,540 @@
+use crate::{
+    io::{open, Locs},
+    output::read_table,
+    LDhatResult as Result,
+};
+use polars::prelude::*;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Recombination rate between two consecutive SNPs: mean, median and 95% interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub mean: f64,
+    pub median: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Rates between consecutive SNPs, one fewer than the positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateMap {
+    pub positions: Vec<f64>,
+    pub rates: Vec<Rate>,
+    /// Chromosome or contig, from [`Locs::contig`] or `stitch --chromosome`
+    pub contig: Option<String>,
+}
+
+/// Consistency of two windows over the SNPs they share.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlap {
+    pub start: f64,
+    pub end: f64,
+    /// Position where the left window stops and the right one takes over
+    pub cut: f64,
+    /// Total of the mean rate times the distance over the overlap, in each window
+    pub left: f64,
+    pub right: f64,
+}
+
+impl Overlap {
+    /// Difference of the two totals relative to the larger one.
+    pub fn relative_difference(&self) -> f64 {
+        let max = self.left.max(self.right);
+        if max == 0. {
+            0.
+        } else {
+            (self.left - self.right).abs() / max
+        }
+    }
+}
+
+/// Read the rate summary `res.txt` that LDhat `stat` writes from the `rates.txt` of
+/// `interval` or `rhomap`, for the SNPs of `locs`.
+///
+/// After a header, each row is `Loci Mean Median L95 U95`. Rows with a negative locus
+/// hold region totals and are skipped; the others give the rate from the SNP at position
+/// `Loci` to the next, and must follow the positions of `locs`.
+pub fn read_rate_map(path: &Path, locs: &Locs) -> Result<RateMap> {
+    parse_rate_map(open(path)?, locs)
+}
+
+fn parse_rate_map(reader: impl BufRead, locs: &Locs) -> Result<RateMap> {
+    let mut rates = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let fields: Vec<f64> = match line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<std::result::Result<_, _>>()
+        {
+            Ok(fields) => fields,
+            // Header
+            Err(_) => continue,
+        };
+        match fields[..] {
+            [] => continue,
+            [locus, ..] if locus < 0. => continue,
+            [locus, mean, median, lower, upper, ..] => {
+                // `stat` prints positions with three decimals
+                match locs.data.get(rates.len()) {
+                    Some(&position) if (locus - position).abs() <= 1e-3 => {}
+                    position => {
+                        return Err(anyhow::anyhow!(
+                            "Rate map row at {} does not match SNP {} of locs at {:?}",
+                            locus,
+                            rates.len() + 1,
+                            position
+                        ))
+                    }
+                }
+                rates.push(Rate {
+                    mean,
+                    median,
+                    lower,
+                    upper,
+                })
+            }
+            _ => return Err(anyhow::anyhow!("Malformed rate map line: {}", line)),
+        }
+    }
+    let nsnps = locs.data.len();
+    // `stat` may also report the SNP after the last interval
+    if rates.len() == nsnps {
+        rates.pop();
+    }
+    if rates.len() + 1 != nsnps {
+        return Err(anyhow::anyhow!(
+            "{} rates for {} SNPs in locs",
+            rates.len(),
+            nsnps
+        ));
+    }
+    Ok(RateMap {
+        positions: locs.data.clone(),
+        rates,
+        contig: locs.contig.clone(),
+    })
+}
+
+impl RateMap {
+    /// Sum of the mean rate times the distance between SNPs `start` and `end`.
+    fn total(&self, start: usize, end: usize) -> f64 {
+        (start..end)
+            .map(|k| self.rates[k].mean * (self.positions[k + 1] - self.positions[k]))
+            .sum()
+    }
+
+    /// One row per interval between consecutive SNPs, on the contig if known.
+    pub fn table(&self) -> Result<DataFrame> {
+        let column = |f: fn(&Rate) -> f64| self.rates.iter().map(f).collect::<Vec<_>>();
+        let n = self.rates.len();
+        Ok(df!(
+            "chromosome" => vec![self.contig.as_deref(); n],
+            "start" => &self.positions[..n],
+            "end" => &self.positions[1..],
+            "mean" => column(|r| r.mean),
+            "median" => column(|r| r.median),
+            "lower" => column(|r| r.lower),
+            "upper" => column(|r| r.upper),
+        )?)
+    }
+}
+
+/// Read a rate map table written by `stitch`, in any format of [`write_table`].
+///
+/// [`write_table`]: crate::output::write_table
+pub fn read_map_table(path: &Path) -> Result<RateMap> {
+    RateMap::from_table(&read_table(path)?)
+        .map_err(|e| anyhow::anyhow!("Not a rate map table {}: {}", path.display(), e))
+}
+
+impl RateMap {
+    /// Inverse of [`RateMap::table`].
+    pub fn from_table(df: &DataFrame) -> Result<Self> {
+        let column = |name: &str| -> Result<Vec<f64>> {
+            df.column(name)?
+                .cast(&DataType::Float64)?
+                .f64()?
+                .into_iter()
+                .map(|v| v.ok_or_else(|| anyhow::anyhow!("Missing value in column {}", name)))
+                .collect()
+        };
+        let (starts, ends) = (column("start")?, column("end")?);
+        let (mean, median) = (column("mean")?, column("median")?);
+        let (lower, upper) = (column("lower")?, column("upper")?);
+        for k in 1..starts.len() {
+            if starts[k] != ends[k - 1] {
+                return Err(anyhow::anyhow!("Gap in rate map before {}", starts[k]));
+            }
+        }
+        let mut positions = starts;
+        positions.extend(ends.last());
+        // Tables written before the column was added have no contig
+        let mut contigs: Vec<Option<String>> = match df.column("chromosome") {
+            Ok(column) => column
+                .cast(&DataType::Utf8)?
+                .utf8()?
+                .into_iter()
+                .map(|c| c.filter(|c| !c.is_empty()).map(String::from))
+                .collect(),
+            Err(_) => vec![],
+        };
+        contigs.dedup();
+        let contig = match contigs.as_slice() {
+            [] => None,
+            [contig] => contig.clone(),
+            _ => return Err(anyhow::anyhow!("Rate map spans several chromosomes")),
+        };
+        let rates = (0..mean.len())
+            .map(|k| Rate {
+                mean: mean[k],
+                median: median[k],
+                lower: lower[k],
+                upper: upper[k],
+            })
+            .collect();
+        Ok(RateMap {
+            positions,
+            rates,
+            contig,
+        })
+    }
+}
+
+/// Map function turning a recombination fraction into a map distance.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum MapFunction {
+    /// No crossover interference
+    Haldane,
+    /// Interference decreasing with distance
+    Kosambi,
+}
+
+impl MapFunction {
+    /// Map distance in Morgans of recombination fraction `c`, infinite from 0.5.
+    pub fn distance(&self, c: f64) -> f64 {
+        if c >= 0.5 {
+            return f64::INFINITY;
+        }
+        match self {
+            MapFunction::Haldane => -0.5 * (1. - 2. * c).ln(),
+            MapFunction::Kosambi => 0.25 * ((1. + 2. * c) / (1. - 2. * c)).ln(),
+        }
+    }
+}
+
+impl RateMap {
+    /// Cumulative map position in cM of every SNP, taking rho = 4 Ne c for each interval.
+    pub fn genetic_map(&self, ne: f64, function: MapFunction) -> Result<Vec<f64>> {
+        let mut cm = Vec::with_capacity(self.positions.len());
+        let mut total = 0.;
+        cm.push(total);
+        for k in 0..self.rates.len() {
+            let c = self.total(k, k + 1) / (4. * ne);
+            let d = function.distance(c);
+            if !d.is_finite() {
+                return Err(anyhow::anyhow!(
+                    "Recombination fraction {} from {} reaches 0.5, Ne is too small",
+                    c,
+                    self.positions[k]
+                ));
+            }
+            total += 100. * d;
+            cm.push(total);
+        }
+        Ok(cm)
+    }
+
+    /// The Ne for which the map is `total_cm` long, found by bisection of log Ne.
+    pub fn calibrate_ne(&self, total_cm: f64, function: MapFunction) -> Result<f64> {
+        let length = |log_ne: f64| {
+            self.genetic_map(10f64.powf(log_ne), function)
+                .map_or(f64::INFINITY, |cm| *cm.last().unwrap())
+        };
+        let (mut lo, mut hi) = (-3f64, 12f64);
+        if !(length(lo) >= total_cm && length(hi) <= total_cm) {
+            return Err(anyhow::anyhow!(
+                "No Ne between 1e{} and 1e{} gives a {} cM map",
+                lo,
+                hi,
+                total_cm
+            ));
+        }
+        for _ in 0..100 {
+            let mid = (lo + hi) / 2.;
+            if length(mid) > total_cm {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(10f64.powf((lo + hi) / 2.))
+    }
+}
+
+/// Run of consecutive intervals with a rate well above the background.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotspot {
+    pub start: f64,
+    pub end: f64,
+    /// Mean rate over the run
+    pub rate: f64,
+    /// `rate` relative to the background rate of the map
+    pub fold: f64,
+}
+
+impl RateMap {
+    /// Mean rate over the whole map, weighting each interval by its length.
+    pub fn background(&self) -> f64 {
+        let length = self.positions.last().unwrap_or(&0.) - self.positions.first().unwrap_or(&0.);
+        if length > 0. {
+            self.total(0, self.rates.len()) / length
+        } else {
+            0.
+        }
+    }
+
+    /// Merge consecutive intervals whose mean rate is at least `fold` times the background.
+    pub fn hotspots(&self, fold: f64) -> Vec<Hotspot> {
+        let threshold = fold * self.background();
+        let mut hotspots = vec![];
+        let mut k = 0;
+        while k < self.rates.len() {
+            if self.rates[k].mean < threshold || self.rates[k].mean == 0. {
+                k += 1;
+                continue;
+            }
+            let start = k;
+            while k < self.rates.len() && self.rates[k].mean >= threshold {
+                k += 1;
+            }
+            let length = self.positions[k] - self.positions[start];
+            let rate = if length > 0. {
+                self.total(start, k) / length
+            } else {
+                self.rates[start].mean
+            };
+            hotspots.push(Hotspot {
+                start: self.positions[start],
+                end: self.positions[k],
+                rate,
+                fold: rate / self.background(),
+            });
+        }
+        hotspots
+    }
+
+    /// Write the mean rate of every interval as a bedGraph track on `contig`.
+    ///
+    /// Positions are multiplied by `scale` to give base pairs, and rates are per kb.
+    /// The interval between SNPs at `a` and `b` becomes the BED interval `a b`, that is
+    /// bases `a + 1..=b`, so that consecutive intervals abut without overlapping.
+    pub fn write_bedgraph(&self, contig: &str, scale: f64, ofp: &mut impl Write) -> Result<()> {
+        writeln!(
+            ofp,
+            "track type=bedGraph name=\"{} rho/kb\" description=\"Mean rho per kb\"",
+            contig
+        )?;
+        for (k, rate) in self.rates.iter().enumerate() {
+            writeln!(
+                ofp,
+                "{}\t{}\t{}\t{:.6}",
+                contig,
+                (self.positions[k] * scale).round() as u64,
+                (self.positions[k + 1] * scale).round() as u64,
+                rate.mean * 1000. / scale
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `hotspots` on `contig` as BED, in the coordinates of [`RateMap::write_bedgraph`].
+///
+/// The score is the fold enrichment times 100, capped at the BED maximum of 1000.
+pub fn write_hotspots_bed(
+    hotspots: &[Hotspot],
+    contig: &str,
+    scale: f64,
+    ofp: &mut impl Write,
+) -> Result<()> {
+    writeln!(
+        ofp,
+        "track name=\"{} hotspots\" description=\"Intervals of elevated recombination rate\"",
+        contig
+    )?;
+    for (i, hotspot) in hotspots.iter().enumerate() {
+        writeln!(
+            ofp,
+            "{}\t{}\t{}\thotspot{}\t{}",
+            contig,
+            (hotspot.start * scale).round() as u64,
+            (hotspot.end * scale).round() as u64,
+            i + 1,
+            (hotspot.fold * 100.).round().min(1000.) as u32
+        )?;
+    }
+    Ok(())
+}
+
+/// Join the rate maps of windows in chromosome order, each sharing SNPs with the next.
+///
+/// At each boundary the shared SNPs must have the same positions in both windows, and
+/// `overlap` of them when given. The left window is kept up to the middle shared SNP and
+/// the right one from there, trimming the rest of the overlap from both.
+pub fn stitch(windows: &[RateMap], overlap: Option<usize>) -> Result<(RateMap, Vec<Overlap>)> {
+    let mut windows = windows.iter();
+    let mut map = windows
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No windows to stitch"))?
+        .clone();
+    let mut overlaps = vec![];
+    for (i, right) in windows.enumerate() {
+        let first = right.positions[0];
+        let shared = map.positions.iter().filter(|&&p| p >= first).count();
+        let start = map.positions.len() - shared;
+        if shared == 0
+            || overlap.map_or(false, |o| o != shared)
+            || right.positions.len() < shared
+            || map.positions[start..] != right.positions[..shared]
+        {
+            return Err(anyhow::anyhow!(
+                "Windows {} and {} do not share {} SNPs at the same positions",
+                i + 1,
+                i + 2,
+                overlap.map_or("any".to_string(), |o| o.to_string())
+            ));
+        }
+        let cut = shared / 2;
+        overlaps.push(Overlap {
+            start: first,
+            end: right.positions[shared - 1],
+            cut: right.positions[cut],
+            left: map.total(start, map.positions.len() - 1),
+            right: right.total(0, shared - 1),
+        });
+        map.positions.truncate(start + cut);
+        map.rates.truncate(start + cut);
+        map.positions.extend_from_slice(&right.positions[cut..]);
+        map.rates.extend_from_slice(&right.rates[cut..]);
+    }
+    Ok((map, overlaps))
+}
+
+#[test]
+fn test_stitch() {
+    let res = |first: f64, rates: &[f64]| {
+        let mut content = "Loci\tMean_rho\tMedian\tL95\tU95\n-1.000\t9\t9\t9\t9\n".to_string();
+        for (k, r) in rates.iter().enumerate() {
+            let locus = first + k as f64;
+            content.push_str(&format!("{:.3}\t{}\t{}\t{}\t{}\n", locus, r, r, r, r));
+        }
+        content
+    };
+    let locs = |data: Vec<f64>| Locs {
+        length: *data.last().unwrap(),
+        data,
+        model: crate::io::Model::CrossingOver,
+        contig: None,
+    };
+    let left = parse_rate_map(
+        res(1., &[1., 2., 3., 4.]).as_bytes(),
+        &locs(vec![1., 2., 3., 4., 5.]),
+    )
+    .unwrap();
+    let right = parse_rate_map(
+        res(3., &[5., 6., 7., 8.]).as_bytes(),
+        &locs(vec![3., 4., 5., 6., 7.]),
+    )
+    .unwrap();
+    assert!(parse_rate_map(
+        res(2., &[5., 6., 7., 8.]).as_bytes(),
+        &locs(vec![3., 4., 5., 6., 7.])
+    )
+    .is_err());
+    let (mut map, overlaps) = stitch(&[left.clone(), right.clone()], Some(3)).unwrap();
+    assert_eq!(map.positions, vec![1., 2., 3., 4., 5., 6., 7.]);
+    let means: Vec<f64> = map.rates.iter().map(|r| r.mean).collect();
+    assert_eq!(means, vec![1., 2., 3., 6., 7., 8.]);
+    assert_eq!(overlaps[0].cut, 4.);
+    assert_eq!((overlaps[0].left, overlaps[0].right), (7., 11.));
+    assert!(stitch(&[left, right], Some(2)).is_err());
+
+    map.contig = Some("chr2".to_string());
+    for format in [
+        crate::output::TableFormat::Tsv,
+        crate::output::TableFormat::Parquet,
+        crate::output::TableFormat::Ipc,
+    ] {
+        let stem = std::env::temp_dir().join("ldhat_test_stitch_map");
+        let path =
+            crate::output::write_table(stem.to_str().unwrap(), &mut map.table().unwrap(), format)
+                .unwrap();
+        assert_eq!(read_map_table(&path).unwrap(), map);
+    }
+}
+
+#[test]
+fn test_genetic_map() {
+    let map = RateMap {
+        positions: vec![0., 1000., 3000.],
+        rates: vec![
+            Rate {
+                mean: 0.4,
+                median: 0.4,
+                lower: 0.,
+                upper: 1.,
+            };
+            2
+        ],
+        contig: None,
+    };
+    // rho 400 and 800, so c = 0.01 and 0.02 for Ne = 10000
+    let cm = map.genetic_map(10000., MapFunction::Haldane).unwrap();
+    let haldane = |c: f64| -50. * (1. - 2. * c).ln();
+    assert!((cm[2] - haldane(0.01) - haldane(0.02)).abs() < 1e-9);
+    assert!(map.genetic_map(100., MapFunction::Kosambi).is_err());
+    let ne = map.calibrate_ne(cm[2], MapFunction::Haldane).unwrap();
+    assert!((ne - 10000.).abs() < 1e-3);
+}
+
+#[test]
+fn test_hotspots() {
+    let rate = |mean| Rate {
+        mean,
+        median: mean,
+        lower: mean,
+        upper: mean,
+    };
+    let map = RateMap {
+        positions: vec![0., 10., 11., 12., 30.],
+        rates: vec![rate(1.), rate(50.), rate(40.), rate(1.)],
+        contig: Some("chr1".to_string()),
+    };
+    // (10 + 50 + 40 + 18) / 30
+    let background = 118. / 30.;
+    assert!((map.background() - background).abs() < 1e-12);
+    let hotspots = map.hotspots(5.);
+    assert_eq!(hotspots.len(), 1);
+    assert_eq!(
+        (hotspots[0].start, hotspots[0].end, hotspots[0].rate),
+        (10., 12., 45.)
+    );
+    assert!(map.hotspots(20.).is_empty());
+
+    let mut bedgraph = vec![];
+    map.write_bedgraph("chr1", 1000., &mut bedgraph).unwrap();
+    let bedgraph = String::from_utf8(bedgraph).unwrap();
+    assert_eq!(
+        bedgraph.lines().nth(2),
+        Some("chr1\t10000\t11000\t50.000000")
+    );
+    let mut bed = vec![];
+    write_hotspots_bed(&hotspots, "chr1", 1000., &mut bed).unwrap();
+    let bed = String::from_utf8(bed).unwrap();
+    assert_eq!(
+        bed.lines().nth(1),
+        Some("chr1\t10000\t12000\thotspot1\t1000")
+    );
+}
@@ -0,0 +1,129 @@
+use crate::commands::GlobalOptions;
+use crate::LDhatResult as Result;
+use clap::ValueEnum;
+use polars::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Format of the summaries written beside the legacy text files.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum OutputFormat {
+    /// Legacy text files only
+    Text,
+    /// Also write a JSON summary and tables
+    Structured,
+}
+
+impl OutputFormat {
+    pub fn is_structured(&self) -> bool {
+        *self == OutputFormat::Structured
+    }
+}
+
+/// File format of the tables written with `--output-format structured`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TableFormat {
+    /// Tab-separated values
+    Tsv,
+    /// Apache Parquet
+    Parquet,
+    /// Arrow IPC (Feather v2)
+    Ipc,
+}
+
+impl TableFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Tsv => "tsv",
+            TableFormat::Parquet => "parquet",
+            TableFormat::Ipc => "arrow",
+        }
+    }
+}
+
+/// Write a table to `{stem}.{extension}` and return the path written.
+pub fn write_table(stem: &str, df: &mut DataFrame, format: TableFormat) -> Result<PathBuf> {
+    let path = PathBuf::from(format!("{}.{}", stem, format.extension()));
+    match format {
+        TableFormat::Tsv => write_tsv(&path, df)?,
+        TableFormat::Parquet => write_parquet(&path, df)?,
+        TableFormat::Ipc => write_ipc(&path, df)?,
+    }
+    Ok(path)
+}
+
+/// Read a table written by [`write_table`], in the format given by its extension.
+pub fn read_table(path: &Path) -> Result<DataFrame> {
+    let df = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("parquet") => ParquetReader::new(File::open(path)?).finish()?,
+        Some("arrow") => IpcReader::new(File::open(path)?).finish()?,
+        _ => CsvReader::from_path(path)?
+            .has_header(true)
+            .with_delimiter(b'\t')
+            .finish()?,
+    };
+    Ok(df)
+}
+
+/// Record of one run, enough to regenerate its outputs exactly.
+#[derive(Serialize, Debug)]
+pub struct Manifest<'a, T: Serialize> {
+    pub command: &'static str,
+    pub version: &'static str,
+    /// Seed of the random number generator, drawn from entropy when not given
+    pub seed: Option<u64>,
+    pub options: &'a T,
+    /// Options shared by every subcommand, which also decide the files written
+    pub global: &'a GlobalOptions,
+    pub inputs: Vec<InputFile>,
+    pub outputs: Vec<PathBuf>,
+}
+
+/// Input file of a run and its SHA-256 checksum.
+#[derive(Serialize, Debug)]
+pub struct InputFile {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+impl InputFile {
+    pub fn new(path: &Path) -> Result<Self> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut File::open(path)?, &mut hasher)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+/// Write scalar results and run metadata as pretty-printed JSON.
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut file = crate::io::create(path)?;
+    serde_json::to_writer_pretty(&mut file, value)?;
+    file.finish()
+}
+
+/// Write a table as tab-separated values with a header line.
+pub fn write_tsv(path: &Path, df: &mut DataFrame) -> Result<()> {
+    let mut file = crate::io::create(path)?;
+    CsvWriter::new(&mut file)
+        .has_header(true)
+        .with_delimiter(b'\t')
+        .finish(df)?;
+    file.finish()
+}
+
+pub fn write_parquet(path: &Path, df: &mut DataFrame) -> Result<()> {
+    let file = File::create(path)?;
+    ParquetWriter::new(file).finish(df)?;
+    Ok(())
+}
+
+pub fn write_ipc(path: &Path, df: &mut DataFrame) -> Result<()> {
+    let mut file = File::create(path)?;
+    IpcWriter::new(&mut file).finish(df)?;
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! Python bindings, built with `maturin build --features python`.
+use crate::convert::ConvertOptions;
+use crate::io::{self, Locs, Ploidy, SeqFormat, Seqs};
+use clap::ValueEnum;
+use numpy::{IntoPyArray, PyArray1, PyArray2};
+use polars::prelude::{DataFrame, DataType, UInt32Type, UInt8Type};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rand::{rngs::StdRng, SeedableRng};
+use std::path::PathBuf;
+
+/// Aligned sequences, one column per sample and one row per site.
+#[pyclass(name = "Seqs")]
+pub struct PySeqs {
+    inner: Seqs,
+}
+
+#[pymethods]
+impl PySeqs {
+    #[getter]
+    fn names(&self) -> Vec<String> {
+        self.inner.names().into_iter().map(String::from).collect()
+    }
+
+    #[getter]
+    fn ploidy(&self) -> usize {
+        self.inner.ploidy as usize
+    }
+
+    /// Base codes as a sites × samples `uint8` array.
+    fn genotypes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<u8>> {
+        let data = self
+            .inner
+            .data
+            .to_ndarray::<UInt8Type>()
+            .map_err(anyhow::Error::from)?;
+        Ok(data.into_pyarray(py))
+    }
+
+    /// Allele counts as a sites × 5 `uint32` array with columns N, T, C, A, G.
+    fn allele_count<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<u32>> {
+        let counts = self
+            .inner
+            .allele_count(None)?
+            .to_ndarray::<UInt32Type>()
+            .map_err(anyhow::Error::from)?;
+        Ok(counts.into_pyarray(py))
+    }
+}
+
+/// Positions of segregating sites.
+#[pyclass(name = "Locs")]
+pub struct PyLocs {
+    inner: Locs,
+}
+
+#[pymethods]
+impl PyLocs {
+    #[getter]
+    fn positions<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        self.inner.data.clone().into_pyarray(py)
+    }
+
+    #[getter]
+    fn length(&self) -> f64 {
+        self.inner.length
+    }
+
+    #[getter]
+    fn model(&self) -> String {
+        self.inner.model.to_string()
+    }
+
+    #[getter]
+    fn contig(&self) -> Option<String> {
+        self.inner.contig.clone()
+    }
+}
+
+#[pyfunction]
+fn read_sites(path: PathBuf) -> PyResult<PySeqs> {
+    Ok(PySeqs {
+        inner: io::read_sites(&path)?,
+    })
+}
+
+#[pyfunction]
+fn read_locs(path: PathBuf) -> PyResult<PyLocs> {
+    Ok(PyLocs {
+        inner: io::read_locs(&path)?,
+    })
+}
+
+/// Read an alignment in any format of `convert --format`: `ldhat`, `genotypes`, `parquet`,
+/// `phylip`, `nexus` or `plink`, guessed from the file extension if `format` is absent.
+#[pyfunction]
+#[pyo3(signature = (path, format = None, ploidy = 1))]
+fn read_seqs(path: PathBuf, format: Option<&str>, ploidy: usize) -> PyResult<PySeqs> {
+    let format = match format {
+        Some(format) => SeqFormat::from_str(format, true).map_err(|e| anyhow::anyhow!(e))?,
+        None => SeqFormat::from_path(&path),
+    };
+    let ploidy: Ploidy = ploidy.to_string().parse().map_err(anyhow::Error::from)?;
+    Ok(PySeqs {
+        inner: io::read_seqs(&path, format, ploidy)?,
+    })
+}
+
+/// Columns of `df` as a pandas `DataFrame`.
+fn to_frame(py: Python, df: &DataFrame) -> PyResult<PyObject> {
+    let columns = PyDict::new(py);
+    for series in df.get_columns() {
+        let values = match series.dtype() {
+            DataType::UInt32 => series
+                .u32()
+                .map(|c| c.into_iter().collect::<Vec<_>>().into_py(py)),
+            DataType::Float64 => series
+                .f64()
+                .map(|c| c.into_iter().collect::<Vec<_>>().into_py(py)),
+            DataType::Boolean => series
+                .bool()
+                .map(|c| c.into_iter().collect::<Vec<_>>().into_py(py)),
+            _ => series
+                .cast(&DataType::Utf8)
+                .and_then(|s| Ok(s.utf8()?.into_iter().collect::<Vec<_>>().into_py(py))),
+        }
+        .map_err(anyhow::Error::from)?;
+        columns.set_item(series.name(), values)?;
+    }
+    Ok(py
+        .import("pandas")?
+        .call_method1("DataFrame", (columns,))?
+        .into_py(py))
+}
+
+/// Filter sites and subsample sequences like the `convert` subcommand.
+///
+/// Returns the kept `Seqs` and `Locs`, the site and sample reports as frames
+/// with the columns of the `site_filter` and `sample_filter` tables, and the seed used.
+#[pyfunction]
+#[pyo3(signature = (
+    seqs,
+    locs,
+    only2 = false,
+    freqcut = 0.,
+    missfreqcut = 1.,
+    sites = None,
+    nout = None,
+    seed = None,
+    region = None,
+    include = None,
+    exclude = Vec::new(),
+    sample_missfreqcut = 1.,
+    hwe = None,
+    min_spacing = 0.,
+    split_multiallelic = false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn convert(
+    py: Python,
+    seqs: &PySeqs,
+    locs: &PyLocs,
+    only2: bool,
+    freqcut: f64,
+    missfreqcut: f64,
+    sites: Option<(usize, usize)>,
+    nout: Option<usize>,
+    seed: Option<u64>,
+    region: Option<(f64, f64)>,
+    include: Option<Vec<(f64, f64)>>,
+    exclude: Vec<(f64, f64)>,
+    sample_missfreqcut: f64,
+    hwe: Option<f64>,
+    min_spacing: f64,
+    split_multiallelic: bool,
+) -> PyResult<(PySeqs, PyLocs, PyObject, PyObject, u64)> {
+    let options = ConvertOptions {
+        only2,
+        freqcut,
+        missfreqcut,
+        sites,
+        nout,
+        region,
+        include,
+        exclude,
+        sample_missfreqcut,
+        hwe,
+        min_spacing,
+        split_multiallelic,
+    };
+    // Drawn from entropy when not given, and returned so the run can be repeated
+    let seed = seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let converted = crate::convert::convert(&seqs.inner, &locs.inner, &options, &mut rng)?;
+    let site_frame = to_frame(py, &converted.site_table()?)?;
+    let sample_frame = to_frame(py, &converted.sample_table()?)?;
+    Ok((
+        PySeqs {
+            inner: converted.seqs,
+        },
+        PyLocs {
+            inner: converted.locs,
+        },
+        site_frame,
+        sample_frame,
+        seed,
+    ))
+}
+
+#[pymodule]
+fn ldhat(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PySeqs>()?;
+    m.add_class::<PyLocs>()?;
+    m.add_function(wrap_pyfunction!(read_sites, m)?)?;
+    m.add_function(wrap_pyfunction!(read_locs, m)?)?;
+    m.add_function(wrap_pyfunction!(read_seqs, m)?)?;
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    Ok(())
+}